@@ -106,13 +106,26 @@ export function initBuffers(glCtx) {
     };
 }
 
-export function updateBufferData(glCtx, buffer, bufferData) {
+// Upload vertex data to a buffer, allocating it. The data is uploaded as is,
+// so it can be a view into wasm memory, which is only valid until the next
+// call into wasm.
+export function updateBufferData(glCtx, buffer, bufferData, usage = glCtx.STATIC_DRAW) {
     // Bind (select) the provided buffer
     glCtx.bindBuffer(glCtx.ARRAY_BUFFER, buffer);
+    glCtx.bufferData(glCtx.ARRAY_BUFFER, bufferData, usage);
+}
 
-    glCtx.bufferData(glCtx.ARRAY_BUFFER,
-                new Float32Array(bufferData),
-                glCtx.STATIC_DRAW);
+// Allocate a buffer of a size in bytes, without uploading any data
+export function allocateBuffer(glCtx, buffer, byteLength, usage = glCtx.STATIC_DRAW) {
+    glCtx.bindBuffer(glCtx.ARRAY_BUFFER, buffer);
+    glCtx.bufferData(glCtx.ARRAY_BUFFER, byteLength, usage);
+}
+
+// Overwrite the vertex data of a buffer, which must already be allocated
+// with the size of the data. Faster than reallocating it every frame.
+export function updateBufferSubData(glCtx, buffer, bufferData) {
+    glCtx.bindBuffer(glCtx.ARRAY_BUFFER, buffer);
+    glCtx.bufferSubData(glCtx.ARRAY_BUFFER, 0, bufferData);
 }
 
 // Compile a shader from source
@@ -0,0 +1,47 @@
+/// An inclusive bounding box of cells in padded coordinates.
+/// Used to only step the part of the simulation which can change.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Region {
+    pub x0: usize,
+    pub y0: usize,
+    pub x1: usize,
+    pub y1: usize,
+}
+
+impl Region {
+    /// Create a region covering the whole unpadded grid
+    pub fn full(width: usize, height: usize) -> Region {
+        return Region { x0: 1, y0: 1, x1: width, y1: height };
+    }
+
+    /// Create a region containing no cells
+    pub fn empty() -> Region {
+        return Region { x0: usize::MAX, y0: usize::MAX, x1: 0, y1: 0 };
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.x0 > self.x1 || self.y0 > self.y1;
+    }
+
+    /// Grow the region to contain a cell
+    pub fn include(&mut self, x: usize, y: usize) {
+        self.x0 = self.x0.min(x);
+        self.y0 = self.y0.min(y);
+        self.x1 = self.x1.max(x);
+        self.y1 = self.y1.max(y);
+    }
+
+    /// Grow the region by a margin in every direction, clamped to the
+    /// unpadded grid. A margin of one covers all hexagonal neighbours.
+    pub fn grow(&self, margin: usize, width: usize, height: usize) -> Region {
+        if self.is_empty() {
+            return *self;
+        }
+        return Region {
+            x0: self.x0.saturating_sub(margin).max(1),
+            y0: self.y0.saturating_sub(margin).max(1),
+            x1: (self.x1 + margin).min(width),
+            y1: (self.y1 + margin).min(height),
+        };
+    }
+}
@@ -0,0 +1,307 @@
+use super::growth_model::GrowthModel;
+use super::image_export::{self, ImageOptions};
+use super::morphology::{self, Morphology};
+use super::sim::SnowflakeSim;
+use super::symmetry_score;
+use super::termination::{GrowthStatus, StopConditions};
+
+use std::io::{self, Write};
+
+/// Glyphs of the labels of contact sheets, 3 pixels wide and 5 high,
+/// where every row is 3 bits with the leftmost pixel as the highest bit
+static GLYPHS: [(char, [u8; 5]); 17] = [
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b111, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b001, 0b001, 0b001]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    ('=', [0b000, 0b111, 0b000, 0b111, 0b000]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    ('a', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('b', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('g', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('s', [0b011, 0b100, 0b010, 0b001, 0b110]),
+];
+
+/// A sweep over the parameters of Reiters model. Every combination of
+/// the parameter values and seeds is simulated from a single frozen cell
+/// in the center of the grid, until a stop condition is met.
+#[derive(Clone, Debug)]
+pub struct Sweep {
+    /// Size of the simulation grid of every run
+    pub width: usize,
+    pub height: usize,
+    pub alpha: Vec<f64>,
+    pub beta: Vec<f64>,
+    pub gamma: Vec<f64>,
+    /// Random seeds, which only change the crystal if a parameter
+    /// is randomized
+    pub seeds: Vec<u64>,
+    /// Other parameters set for every run, such as gamma_rand.
+    /// Unknown parameters are ignored.
+    pub params: Vec<(String, f64)>,
+    pub stop_conditions: StopConditions,
+    /// Iterations after which a run stops without meeting a stop condition
+    pub max_iterations: usize,
+    /// Size of the images of the runs in pixels, see `render_contact_sheet`
+    pub tile_size: u32,
+}
+
+impl Default for Sweep {
+    fn default() -> Sweep {
+        return Sweep {
+            width: 200,
+            height: 200,
+            alpha: vec![1.0],
+            beta: vec![0.4],
+            gamma: vec![0.0001],
+            seeds: vec![0],
+            params: Vec::new(),
+            stop_conditions: StopConditions {
+                edge_distance: Some(2),
+                stall_iterations: Some(1000),
+                ..StopConditions::default()
+            },
+            max_iterations: 20000,
+            tile_size: 200,
+        };
+    }
+}
+
+/// The parameters of a single run of a sweep
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SweepRun {
+    pub alpha: f64,
+    pub beta: f64,
+    pub gamma: f64,
+    pub seed: u64,
+}
+
+/// The crystal grown by a single run of a sweep
+#[derive(Clone, Debug)]
+pub struct SweepResult {
+    pub run: SweepRun,
+    pub iterations: usize,
+    /// Stop condition which was met, `Growing` if the run
+    /// reached the maximum amount of iterations
+    pub status: GrowthStatus,
+    pub morphology: Morphology,
+    /// Overall symmetry index, see `symmetry_score::SymmetryScore`
+    pub symmetry_index: f64,
+    /// RGBA image of the final state, `tile_size` pixels wide and high
+    pub tile: Vec<u8>,
+}
+
+impl Sweep {
+    /// Get every combination of the parameter values and seeds. Seeds
+    /// change fastest, followed by beta, gamma and alpha, so that the
+    /// rows of a contact sheet with a column per beta value and seed
+    /// follow gamma like the phase diagram of Reiters paper.
+    pub fn runs(&self) -> Vec<SweepRun> {
+        let mut runs = Vec::new();
+        for &alpha in &self.alpha {
+            for &gamma in &self.gamma {
+                for &beta in &self.beta {
+                    for &seed in &self.seeds {
+                        runs.push(SweepRun { alpha, beta, gamma, seed });
+                    }
+                }
+            }
+        }
+        return runs;
+    }
+
+    /// Simulate every run of the sweep, in parallel if the `parallel`
+    /// feature is enabled. The results are in the order of `runs`.
+    pub fn run(&self) -> Vec<SweepResult> {
+        let runs = self.runs();
+        #[cfg(feature = "parallel")]
+        {
+            use rayon::prelude::*;
+            return runs.par_iter().map(|run| self.run_single(*run)).collect();
+        }
+        #[cfg(not(feature = "parallel"))]
+        return runs.iter().map(|run| self.run_single(*run)).collect();
+    }
+
+    /// Simulate a single run until a stop condition is met
+    /// or the maximum amount of iterations is reached
+    pub fn run_single(&self, run: SweepRun) -> SweepResult {
+        let mut sim = SnowflakeSim::new(self.width, self.height, run.alpha, run.beta, run.gamma);
+        // The runs are already spread over the threads
+        #[cfg(feature = "parallel")]
+        {
+            sim.parallel = false;
+        }
+        for (name, value) in &self.params {
+            sim.set_param(name, *value);
+        }
+        sim.set_random_seed(run.seed);
+        let seed = (self.width / 2, self.height / 2);
+        sim.set_water(seed.0, seed.1, 1.0);
+        sim.set_stop_conditions(self.stop_conditions);
+        while !sim.status().is_stopped() && sim.iteration_count < self.max_iterations {
+            sim.step();
+        }
+
+        let options = ImageOptions { width: self.tile_size, height: self.tile_size, ..ImageOptions::default() };
+        return SweepResult {
+            run,
+            iterations: sim.iteration_count,
+            status: sim.status(),
+            morphology: morphology::measure(&sim, seed),
+            symmetry_index: symmetry_score::measure(&sim, seed).index,
+            tile: image_export::render_rgba(&sim, &options),
+        };
+    }
+
+    /// Get the amount of contact sheet columns which gives a row per
+    /// gamma value, see `runs`
+    pub fn columns(&self) -> usize {
+        return (self.beta.len() * self.seeds.len()).max(1);
+    }
+}
+
+/// Get `count` values evenly spaced from `start` to `end`, including both.
+/// Values are rounded to 10 decimals, so they are labelled like typed values.
+pub fn linspace(start: f64, end: f64, count: usize) -> Vec<f64> {
+    if count == 1 {
+        return vec![start];
+    }
+    return (0..count)
+        .map(|i| start + (end - start) * i as f64 / (count - 1) as f64)
+        .map(|value| (value * 1e10).round() / 1e10)
+        .collect();
+}
+
+/// Write the parameters and crystal measurements of sweep results as
+/// CSV, with a header and a row per result
+pub fn write_csv(results: &[SweepResult], writer: &mut impl Write) -> io::Result<()> {
+    writeln!(
+        writer,
+        "alpha,beta,gamma,seed,iterations,status,area,perimeter,max_radius,mean_radius,\
+         radius_of_gyration,fractal_dimension,primary_branches,secondary_branches,holes,symmetry_index"
+    )?;
+    for result in results {
+        let (run, morphology) = (&result.run, &result.morphology);
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{},{:.4},{:.4},{:.4},{:.4},{},{},{},{:.4}",
+            run.alpha, run.beta, run.gamma, run.seed, result.iterations, result.status.name(),
+            morphology.area, morphology.perimeter, morphology.max_radius, morphology.mean_radius,
+            morphology.radius_of_gyration, morphology.fractal_dimension, morphology.primary_branches,
+            morphology.secondary_branches, morphology.holes, result.symmetry_index,
+        )?;
+    }
+    return Ok(());
+}
+
+/// Tile the images of sweep results into a PNG contact sheet, with the
+/// parameters of every run written below its image. Seeds are only
+/// written if the results use several.
+///
+/// * `tile_size` - size of the images of the results, see `Sweep::tile_size`
+/// * `columns` - amount of images per row
+pub fn render_contact_sheet(results: &[SweepResult], tile_size: u32, columns: usize) -> Result<Vec<u8>, png::EncodingError> {
+    let tile = tile_size as usize;
+    let columns = columns.max(1);
+    let rows = results.len().div_ceil(columns);
+    // Glyphs are scaled with the tiles, with a pixel of space around them
+    let scale = (tile / 100).max(1);
+    let label_height = 7 * scale;
+    let (width, height) = (columns * tile, rows * (tile + label_height));
+    let mut pixels: Vec<u8> = [0, 0, 0, 255].iter().cycle().take(width * height * 4).copied().collect();
+
+    let several_seeds = results.iter().any(|result| result.run.seed != results[0].run.seed);
+    for (k, result) in results.iter().enumerate() {
+        let (x0, y0) = ((k % columns) * tile, (k / columns) * (tile + label_height));
+        for y in 0..tile {
+            let row = &result.tile[y * tile * 4..(y + 1) * tile * 4];
+            let start = ((y0 + y) * width + x0) * 4;
+            pixels[start..start + tile * 4].copy_from_slice(row);
+        }
+
+        let run = &result.run;
+        let mut label = format!("a={} b={} g={}", run.alpha, run.beta, run.gamma);
+        if several_seeds {
+            label += &format!(" s={}", run.seed);
+        }
+        for (c, character) in label.chars().enumerate() {
+            let glyph = match GLYPHS.iter().find(|(glyph_character, _)| *glyph_character == character) {
+                Some((_, glyph)) => glyph,
+                None => continue,
+            };
+            for (gy, bits) in glyph.iter().enumerate() {
+                for gx in 0..3 {
+                    if bits & (0b100 >> gx) == 0 {
+                        continue;
+                    }
+                    for (px, py) in (0..scale * scale).map(|i| (i % scale, i / scale)) {
+                        let x = (1 + c * 4 + gx) * scale + px;
+                        let y = y0 + tile + (1 + gy) * scale + py;
+                        // Labels wider than the tile are cut off
+                        if x < tile {
+                            let i = (y * width + x0 + x) * 4;
+                            pixels[i..i + 4].copy_from_slice(&[230, 230, 230, 255]);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    return image_export::encode_png(&pixels, width as u32, height as u32);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sweep() {
+        let sweep = Sweep {
+            width: 40,
+            height: 40,
+            beta: linspace(0.4, 0.8, 3),
+            gamma: vec![0.0001, 0.001],
+            seeds: vec![1, 2],
+            params: vec![("gamma_rand".to_string(), 0.2)],
+            max_iterations: 300,
+            tile_size: 50,
+            ..Sweep::default()
+        };
+        assert_eq!(sweep.beta, vec![0.4, 0.6, 0.8]);
+        let runs = sweep.runs();
+        assert_eq!(runs.len(), 12);
+        assert_eq!(runs[1], SweepRun { alpha: 1.0, beta: 0.4, gamma: 0.0001, seed: 2 });
+        assert_eq!(runs[6].gamma, 0.001);
+
+        let results = sweep.run();
+        assert_eq!(results.iter().map(|result| result.run).collect::<Vec<SweepRun>>(), runs);
+        for result in &results {
+            assert!(result.iterations <= 300);
+            assert_eq!(result.status.is_stopped(), result.iterations < 300);
+            assert!(result.morphology.area > 1);
+        }
+        // Higher beta grows faster, up to the edge of the grid
+        assert!(results[4].morphology.area > results[0].morphology.area);
+        assert_eq!(results[4].status, GrowthStatus::Edge);
+
+        let mut csv = Vec::new();
+        write_csv(&results, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().count(), 13);
+        assert!(csv.lines().nth(5).unwrap().starts_with("1,0.8,0.0001,1,"));
+
+        let png = render_contact_sheet(&results, sweep.tile_size, sweep.columns()).unwrap();
+        let decoder = png::Decoder::new(png.as_slice());
+        let reader = decoder.read_info().unwrap();
+        // 6 columns and 2 rows of tiles, with a label of 7 pixels each
+        assert_eq!((reader.info().width, reader.info().height), (300, 114));
+    }
+}
@@ -0,0 +1,453 @@
+#![allow(clippy::needless_return)]
+use snowflake_sim::growth_model::{create_model, GrowthModel};
+use snowflake_sim::image_export::{self, ImageOptions};
+use snowflake_sim::mesh_export::{self, MeshOptions};
+use snowflake_sim::noise::{self, NoiseField, NoiseKind};
+use snowflake_sim::schedule::{Interpolation, Schedule};
+use snowflake_sim::seed_crystal::{CellMask, SeedShape};
+use snowflake_sim::svg_export::{self, SvgOptions};
+use snowflake_sim::sim::{Boundary, RandomMode, SnowflakeSim};
+use snowflake_sim::snapshot;
+use snowflake_sim::termination::StopConditions;
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+static USAGE: &str = "Usage: snowflake [options]
+
+Run a snowflake simulation without a browser and write the result to disk.
+
+Options:
+  --model NAME          Growth model, reiter or gravner (default: reiter)
+  --size WIDTHxHEIGHT   Simulation grid size (default: 200x200)
+  --alpha VALUE         Alpha parameter
+  --beta VALUE          Beta parameter
+  --gamma VALUE         Gamma parameter
+  --alpha-rand RANGE    Alpha randomization range
+  --beta-rand RANGE     Beta randomization range
+  --gamma-rand RANGE    Gamma randomization range
+  --param NAME=VALUE    Set any named parameter of the growth model
+  --seed SEED           Random seed
+  --random MODE         Random number generation of the reiter model,
+                        counter or buffer (default: counter)
+  --noise PARAM=KIND,AMPLITUDE,SCALE[,OCTAVES[,PERIOD]]
+                        Modulate alpha, beta or gamma per cell by value or
+                        perlin noise, with features of SCALE cells changing
+                        over PERIOD iterations (reiter only, can be repeated)
+  --schedule PARAM=INTERPOLATION,ITERATION:VALUE,...
+                        Change a parameter over the iterations through
+                        keyframes, with linear, smoothstep or cubic
+                        interpolation (reiter only, can be repeated)
+  --boundary NAME       Edge of the grid for the reiter model, fixed, circular,
+                        absorbing, periodic or reflecting (default: fixed),
+                        periodic needs an even height
+  --iterations N        Amount of iterations to simulate (default: 1000)
+  --stop CONDITION=VALUE
+                        Stop before all iterations once the crystal is within
+                        edge=STEPS of the edge, nothing froze for
+                        stall=ITERATIONS, the water of the frozen cells
+                        changed by less than mass=FRACTION in 100 iterations
+                        or the crystal reached radius=CELLS (reiter only, can
+                        be repeated)
+  --resume PATH         Continue the simulation of a .snap snapshot, instead
+                        of starting a new one. The model, size, crystal and
+                        symmetry options are ignored.
+  --crystal X,Y         Place a seed crystal cell, can be repeated
+                        (default: the center cell)
+  --seed-shape SHAPE    Place a shaped seed crystal, one of hexagon:X,Y,RADIUS,
+                        line:X,Y,LENGTH,AXIS, ring:X,Y,RADIUS,
+                        scatter:X,Y,RADIUS,COUNT,SEED or mask:X,Y,PATH where
+                        the bright pixels of the PNG image at PATH are frozen.
+                        Can be repeated.
+  --symmetric           Only simulate one symmetric wedge (reiter only)
+  --output PATH         Path of the final state (default: snowflake.txt)
+  --snapshot-every N    Write a snapshot every N iterations
+  --snapshot-dir DIR    Directory for snapshots (default: snapshots)
+  --image-size WxH      Size of PNG images in pixels (default: 1000x1000)
+  --color RRGGBB        Tint color of PNG images (default: 80d1f5)
+  --transparent         Draw PNG images with a transparent background
+  --contour LEVEL       Outline a water level in SVG images, can be repeated
+  --min-level LEVEL     Only include cells with this water level in 3D models
+                        (default: 0, all cells)
+  --ascii               Write STL models as text instead of binary
+  --help                Show this message
+
+States are written as one line of water levels per grid row, as a
+PNG image if the output path ends in .png, as an SVG outline of the
+crystal if it ends in .svg, as a 3D relief if it ends in .stl or .obj,
+or as a complete snapshot which can be resumed if it ends in .snap.
+Snapshots use the same format as the output.";
+
+/// Options for a headless simulation run
+struct Options {
+    model: String,
+    size: (usize, usize),
+    params: Vec<(String, f64)>,
+    seed: Option<u64>,
+    random_mode: Option<RandomMode>,
+    boundary: Option<Boundary>,
+    noise_fields: Vec<(String, NoiseField)>,
+    schedules: Vec<(String, Schedule)>,
+    iterations: usize,
+    stop_conditions: StopConditions,
+    resume: Option<PathBuf>,
+    crystals: Vec<(usize, usize)>,
+    seed_shapes: Vec<SeedShape>,
+    symmetric: bool,
+    output: PathBuf,
+    snapshot_every: Option<usize>,
+    snapshot_dir: PathBuf,
+    image: ImageOptions,
+    svg: SvgOptions,
+    mesh: MeshOptions,
+    ascii_stl: bool,
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return;
+    }
+    let result = parse_args(&args).and_then(|options| run(&options));
+    if let Err(err) = result {
+        eprintln!("error: {}\n\n{}", err, USAGE);
+        std::process::exit(1);
+    }
+}
+
+/// Parse the command line arguments, excluding the program name
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        model: "reiter".to_string(),
+        size: (200, 200),
+        params: Vec::new(),
+        seed: None,
+        random_mode: None,
+        boundary: None,
+        noise_fields: Vec::new(),
+        schedules: Vec::new(),
+        iterations: 1000,
+        stop_conditions: StopConditions::default(),
+        resume: None,
+        crystals: Vec::new(),
+        seed_shapes: Vec::new(),
+        symmetric: false,
+        output: PathBuf::from("snowflake.txt"),
+        snapshot_every: None,
+        snapshot_dir: PathBuf::from("snapshots"),
+        image: ImageOptions::default(),
+        svg: SvgOptions::default(),
+        mesh: MeshOptions::default(),
+        ascii_stl: false,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--symmetric" {
+            options.symmetric = true;
+            continue;
+        }
+        if arg == "--transparent" {
+            options.image.transparent_background = true;
+            continue;
+        }
+        if arg == "--ascii" {
+            options.ascii_stl = true;
+            continue;
+        }
+        let value = args.next().ok_or(format!("missing value for {}", arg))?;
+        match arg.as_str() {
+            "--model" => options.model = value.clone(),
+            "--size" => options.size = parse_pair(value, 'x')?,
+            "--alpha" => options.params.push(("alpha".to_string(), parse_value(value)?)),
+            "--beta" => options.params.push(("beta".to_string(), parse_value(value)?)),
+            "--gamma" => options.params.push(("gamma".to_string(), parse_value(value)?)),
+            "--alpha-rand" => options.params.push(("alpha_rand".to_string(), parse_value(value)?)),
+            "--beta-rand" => options.params.push(("beta_rand".to_string(), parse_value(value)?)),
+            "--gamma-rand" => options.params.push(("gamma_rand".to_string(), parse_value(value)?)),
+            "--param" => {
+                let (name, param) = value.split_once('=').ok_or(format!("invalid parameter '{}'", value))?;
+                options.params.push((name.to_string(), parse_value(param)?));
+            }
+            "--seed" => options.seed = Some(parse_value(value)?),
+            "--random" => {
+                options.random_mode = Some(RandomMode::from_name(value).ok_or(format!("unknown random mode '{}'", value))?);
+            }
+            "--boundary" => {
+                options.boundary = Some(Boundary::from_name(value).ok_or(format!("unknown boundary '{}'", value))?);
+            }
+            "--iterations" => options.iterations = parse_value(value)?,
+            "--stop" => parse_stop_condition(value, &mut options.stop_conditions)?,
+            "--noise" => options.noise_fields.push(parse_noise(value)?),
+            "--schedule" => options.schedules.push(parse_schedule(value)?),
+            "--resume" => options.resume = Some(PathBuf::from(value)),
+            "--crystal" => options.crystals.push(parse_pair(value, ',')?),
+            "--seed-shape" => options.seed_shapes.push(parse_seed_shape(value)?),
+            "--output" => options.output = PathBuf::from(value),
+            "--snapshot-every" => options.snapshot_every = Some(parse_value(value)?),
+            "--snapshot-dir" => options.snapshot_dir = PathBuf::from(value),
+            "--image-size" => {
+                let (width, height) = value.split_once('x').ok_or(format!("invalid value '{}'", value))?;
+                options.image.width = parse_value(width)?;
+                options.image.height = parse_value(height)?;
+                if options.image.width == 0 || options.image.height == 0 {
+                    return Err("image sizes must be larger than zero".to_string());
+                }
+            }
+            "--color" => options.image.tint = parse_color(value)?,
+            "--contour" => options.svg.contour_levels.push(parse_value(value)?),
+            "--min-level" => options.mesh.min_level = parse_value(value)?,
+            _ => return Err(format!("unknown option {}", arg)),
+        }
+    }
+    return Ok(options);
+}
+
+fn parse_value<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    return value.parse().map_err(|_| format!("invalid value '{}'", value));
+}
+
+/// Parse a pair of values separated by `separator`, such as 200x200
+fn parse_pair(value: &str, separator: char) -> Result<(usize, usize), String> {
+    let (a, b) = value.split_once(separator).ok_or(format!("invalid value '{}'", value))?;
+    return Ok((parse_value(a)?, parse_value(b)?));
+}
+
+/// Parse a noise field such as gamma=perlin,0.5,20,3,1000
+fn parse_noise(value: &str) -> Result<(String, NoiseField), String> {
+    let (param, field) = value.split_once('=').ok_or(format!("invalid noise field '{}'", value))?;
+    let parts: Vec<&str> = field.split(',').collect();
+    if parts.len() < 3 || parts.len() > 5 {
+        return Err(format!("invalid noise field '{}'", value));
+    }
+    let kind = NoiseKind::from_name(parts[0]).ok_or(format!("unknown noise kind '{}'", parts[0]))?;
+    let field = NoiseField {
+        kind,
+        amplitude: parse_value(parts[1])?,
+        scale: parse_value(parts[2])?,
+        octaves: parts.get(3).map_or(Ok(1), |octaves| parse_value(octaves))?,
+        period: parts.get(4).map_or(Ok(0.0), |period| parse_value(period))?,
+    };
+    if !field.is_valid() {
+        return Err(format!("invalid noise field '{}', the scale must be positive, the period at least 0 \
+                            and the octaves at most {}", value, noise::MAX_OCTAVES));
+    }
+    return Ok((param.to_string(), field));
+}
+
+/// Parse a schedule such as beta=cubic,0:0.4,2000:0.3
+fn parse_schedule(value: &str) -> Result<(String, Schedule), String> {
+    let (param, keyframes) = value.split_once('=').ok_or(format!("invalid schedule '{}'", value))?;
+    let mut parts = keyframes.split(',');
+    let name = parts.next().unwrap_or_default();
+    let interpolation = Interpolation::from_name(name).ok_or(format!("unknown interpolation '{}'", name))?;
+    let mut schedule = Schedule::new(interpolation);
+    for keyframe in parts {
+        let (iteration, value) = keyframe.split_once(':').ok_or(format!("invalid keyframe '{}'", keyframe))?;
+        schedule.add_keyframe(parse_value(iteration)?, parse_value(value)?);
+    }
+    if schedule.keyframes().is_empty() {
+        return Err(format!("schedule '{}' has no keyframes", value));
+    }
+    if !schedule.is_valid() {
+        return Err(format!("schedule '{}' has values which are not finite", value));
+    }
+    return Ok((param.to_string(), schedule));
+}
+
+/// Parse a stop condition such as edge=2 into the stop conditions
+fn parse_stop_condition(value: &str, conditions: &mut StopConditions) -> Result<(), String> {
+    let (name, limit) = value.split_once('=').ok_or(format!("invalid stop condition '{}'", value))?;
+    if !conditions.set_by_name(name, parse_value(limit)?) {
+        return Err(format!("unknown stop condition '{}'", name));
+    }
+    return Ok(());
+}
+
+/// Parse a seed crystal shape such as hexagon:100,100,3
+fn parse_seed_shape(value: &str) -> Result<SeedShape, String> {
+    let (kind, args) = value.split_once(':').ok_or(format!("invalid seed shape '{}'", value))?;
+    let parts: Vec<&str> = args.split(',').collect();
+    let expected = match kind {
+        "hexagon" | "ring" | "mask" => 3,
+        "line" => 4,
+        "scatter" => 5,
+        _ => return Err(format!("unknown seed shape '{}'", kind)),
+    };
+    if parts.len() != expected {
+        return Err(format!("invalid seed shape '{}'", value));
+    }
+    let (x, y) = (parse_value(parts[0])?, parse_value(parts[1])?);
+    return Ok(match kind {
+        "hexagon" => SeedShape::Hexagon { x, y, radius: parse_value(parts[2])? },
+        "ring" => SeedShape::Ring { x, y, radius: parse_value(parts[2])? },
+        "line" => SeedShape::Line { x, y, length: parse_value(parts[2])?, axis: parse_value(parts[3])? },
+        "scatter" => SeedShape::Scatter {
+            x,
+            y,
+            radius: parse_value(parts[2])?,
+            count: parse_value(parts[3])?,
+            seed: parse_value(parts[4])?,
+        },
+        _ => {
+            let data = std::fs::read(parts[2]).map_err(|err| format!("could not read {}: {}", parts[2], err))?;
+            let mask = CellMask::from_png(&data).map_err(|err| format!("could not decode {}: {}", parts[2], err))?;
+            SeedShape::Mask { x, y, mask }
+        }
+    });
+}
+
+/// Parse a hex color such as 80d1f5 into an RGBA tint
+fn parse_color(value: &str) -> Result<[f32; 4], String> {
+    let digits = value.trim_start_matches('#');
+    let color = u32::from_str_radix(digits, 16).map_err(|_| format!("invalid color '{}'", value))?;
+    if digits.len() != 6 {
+        return Err(format!("invalid color '{}'", value));
+    }
+    let channel = |shift: u32| ((color >> shift) & 0xff) as f32 / 255.0;
+    return Ok([channel(16), channel(8), channel(0), 1.0]);
+}
+
+/// Run the simulation and write the states to disk
+fn run(options: &Options) -> Result<(), String> {
+    let mut sim = match &options.resume {
+        Some(path) => {
+            let data = std::fs::read(path).map_err(|err| format!("could not read {}: {}", path.display(), err))?;
+            snapshot::load_snapshot(&data).map_err(|err| format!("could not load {}: {}", path.display(), err))?
+        }
+        None => new_simulation(options)?,
+    };
+    for (name, value) in &options.params {
+        if !sim.set_param(name, *value) {
+            return Err(format!("the {} model has no parameter '{}'", sim.model_name(), name));
+        }
+    }
+    if let Some(seed) = options.seed {
+        sim.set_random_seed(seed);
+    }
+    if let Some(mode) = options.random_mode {
+        let reiter_sim = sim.as_any_mut().downcast_mut::<SnowflakeSim>()
+            .ok_or("random modes are only supported by the reiter model")?;
+        reiter_sim.set_random_mode(mode);
+    }
+    if let Some(boundary) = options.boundary {
+        let reiter_sim = sim.as_any_mut().downcast_mut::<SnowflakeSim>()
+            .ok_or("boundaries are only supported by the reiter model")?;
+        if !reiter_sim.set_boundary(boundary) {
+            return Err("periodic boundaries need an even grid height".to_string());
+        }
+    }
+    for (param, field) in &options.noise_fields {
+        let reiter_sim = sim.as_any_mut().downcast_mut::<SnowflakeSim>()
+            .ok_or("noise fields are only supported by the reiter model")?;
+        if !reiter_sim.set_noise_field(param, Some(*field)) {
+            return Err(format!("noise fields can not modulate '{}'", param));
+        }
+    }
+    for (param, schedule) in &options.schedules {
+        let reiter_sim = sim.as_any_mut().downcast_mut::<SnowflakeSim>()
+            .ok_or("schedules are only supported by the reiter model")?;
+        if !reiter_sim.set_schedule(param, Some(schedule.clone())) {
+            return Err(format!("the reiter model has no parameter '{}'", param));
+        }
+    }
+
+    if options.stop_conditions.is_enabled() {
+        let reiter_sim = sim.as_any_mut().downcast_mut::<SnowflakeSim>()
+            .ok_or("stop conditions are only supported by the reiter model")?;
+        reiter_sim.set_stop_conditions(options.stop_conditions);
+    }
+
+    let extension = options.output.extension().and_then(|extension| extension.to_str()).unwrap_or("txt");
+    if options.snapshot_every.is_some() {
+        std::fs::create_dir_all(&options.snapshot_dir).map_err(|err| err.to_string())?;
+    }
+    let start = sim.iteration_count();
+    for i in start + 1..start + options.iterations + 1 {
+        sim.step();
+        if let Some(every) = options.snapshot_every {
+            if every > 0 && i % every == 0 {
+                let path = options.snapshot_dir.join(format!("snowflake_{:06}.{}", i, extension));
+                write_state(sim.as_ref(), &path, options)?;
+            }
+        }
+        if let Some(reiter_sim) = sim.as_any().downcast_ref::<SnowflakeSim>() {
+            if reiter_sim.status().is_stopped() {
+                println!("Stopped after {} iterations: {}", i, reiter_sim.status().name());
+                break;
+            }
+        }
+    }
+    return write_state(sim.as_ref(), &options.output, options);
+}
+
+/// Create a new simulation with the seed crystals of the options
+fn new_simulation(options: &Options) -> Result<Box<dyn GrowthModel>, String> {
+    let (width, height) = options.size;
+    let mut sim = create_model(&options.model, width, height)
+        .ok_or(format!("unknown growth model '{}'", options.model))?;
+
+    let mut shapes: Vec<SeedShape> = options.crystals.iter().map(|(x, y)| SeedShape::Single { x: *x, y: *y }).collect();
+    shapes.extend(options.seed_shapes.iter().cloned());
+    if shapes.is_empty() {
+        shapes.push(SeedShape::Single { x: width / 2, y: height / 2 });
+    }
+    if let Some((x, y)) = shapes.iter().map(|shape| shape.center()).find(|(x, y)| *x >= width || *y >= height) {
+        return Err(format!("crystal {},{} is outside of the grid", x, y));
+    }
+    if options.symmetric {
+        let reiter_sim = sim.as_any_mut().downcast_mut::<SnowflakeSim>()
+            .ok_or("symmetric mode is only supported by the reiter model")?;
+        let (cx, cy) = shapes[0].center();
+        reiter_sim.enable_symmetry(cx, cy);
+    }
+    for shape in shapes {
+        for (x, y) in shape.cells(width, height) {
+            sim.set_water(x, y, 1.0);
+        }
+    }
+    return Ok(sim);
+}
+
+fn has_extension(path: &Path, name: &str) -> bool {
+    return path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case(name));
+}
+
+/// Write the state of the simulation as an image or 3D model based on the
+/// extension of the path, otherwise write the water levels as one line per grid row
+fn write_state(sim: &dyn GrowthModel, path: &Path, options: &Options) -> Result<(), String> {
+    let file = File::create(path).map_err(|err| format!("could not create {}: {}", path.display(), err))?;
+    let mut writer = BufWriter::new(file);
+    if has_extension(path, "snap") {
+        writer.write_all(&snapshot::save_snapshot(sim)).map_err(|err| err.to_string())?;
+        return writer.flush().map_err(|err| err.to_string());
+    }
+    if has_extension(path, "png") {
+        let png = image_export::render_png(sim, &options.image).map_err(|err| err.to_string())?;
+        writer.write_all(&png).map_err(|err| err.to_string())?;
+        return writer.flush().map_err(|err| err.to_string());
+    }
+    if has_extension(path, "svg") {
+        writer.write_all(svg_export::render_svg(sim, &options.svg).as_bytes()).map_err(|err| err.to_string())?;
+        return writer.flush().map_err(|err| err.to_string());
+    }
+    if has_extension(path, "stl") || has_extension(path, "obj") {
+        let mesh = mesh_export::build_mesh(sim, &options.mesh);
+        let bytes = if has_extension(path, "obj") {
+            mesh.to_obj().into_bytes()
+        } else if options.ascii_stl {
+            mesh.to_stl_ascii("snowflake").into_bytes()
+        } else {
+            mesh.to_stl_binary()
+        };
+        writer.write_all(&bytes).map_err(|err| err.to_string())?;
+        return writer.flush().map_err(|err| err.to_string());
+    }
+    for y in 0..sim.height() {
+        let row: Vec<String> = (0..sim.width()).map(|x| format!("{:.4}", sim.get_water(x, y))).collect();
+        writeln!(writer, "{}", row.join(" ")).map_err(|err| err.to_string())?;
+    }
+    return writer.flush().map_err(|err| err.to_string());
+}
@@ -0,0 +1,159 @@
+#![allow(clippy::needless_return)]
+use snowflake_sim::batch::{self, Sweep};
+use snowflake_sim::growth_model::GrowthModel;
+use snowflake_sim::sim::SnowflakeSim;
+use snowflake_sim::termination::StopConditions;
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+static USAGE: &str = "Usage: sweep [options]
+
+Simulate Reiters model for every combination of parameter values and seeds,
+and write the crystal measurements as CSV together with a contact sheet image.
+Runs are simulated in parallel when built with the parallel feature.
+
+Options:
+  --size WIDTHxHEIGHT   Simulation grid size of every run (default: 200x200)
+  --alpha VALUES        Alpha values, a list such as 1,1.5 or evenly spaced
+                        values START:END:COUNT (default: 1)
+  --beta VALUES         Beta values, columns of the contact sheet (default: 0.4)
+  --gamma VALUES        Gamma values, rows of the contact sheet (default: 0.0001)
+  --seeds SEEDS         Random seeds, a list such as 1,2 or a range FIRST:LAST
+                        (default: 0)
+  --param NAME=VALUE    Set another parameter for every run, such as
+                        gamma_rand=0.3, can be repeated
+  --stop CONDITION=VALUE
+                        Stop a run once the crystal is within edge=STEPS of the
+                        edge, nothing froze for stall=ITERATIONS, the water of
+                        the frozen cells changed by less than mass=FRACTION in
+                        100 iterations or the crystal reached radius=CELLS,
+                        can be repeated (default: edge=2 and stall=1000)
+  --max-iterations N    Stop a run after N iterations (default: 20000)
+  --tile-size N         Size of a run in the contact sheet in pixels
+                        (default: 200)
+  --csv PATH            Path of the measurements (default: sweep.csv)
+  --sheet PATH          Path of the PNG contact sheet (default: sweep.png)
+  --help                Show this message
+
+For example, the phase diagram of Reiters paper is roughly covered by
+  sweep --beta 0.3:0.9:7 --gamma 0,0.0001,0.001,0.01 --size 300x300";
+
+/// Options for a parameter sweep
+struct Options {
+    sweep: Sweep,
+    csv: PathBuf,
+    sheet: PathBuf,
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return;
+    }
+    let result = parse_args(&args).and_then(|options| run(&options));
+    if let Err(err) = result {
+        eprintln!("error: {}\n\n{}", err, USAGE);
+        std::process::exit(1);
+    }
+}
+
+/// Parse the command line arguments, excluding the program name
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        sweep: Sweep::default(),
+        csv: PathBuf::from("sweep.csv"),
+        sheet: PathBuf::from("sweep.png"),
+    };
+    let param_names = SnowflakeSim::new(1, 1, 1.0, 0.4, 0.0001).param_names();
+    // Stop conditions given on the command line replace the default ones
+    let mut stop_conditions = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args.next().ok_or(format!("missing value for {}", arg))?;
+        match arg.as_str() {
+            "--size" => {
+                let (width, height) = value.split_once('x').ok_or(format!("invalid size '{}'", value))?;
+                options.sweep.width = parse_value(width)?;
+                options.sweep.height = parse_value(height)?;
+            }
+            "--alpha" => options.sweep.alpha = parse_values(value)?,
+            "--beta" => options.sweep.beta = parse_values(value)?,
+            "--gamma" => options.sweep.gamma = parse_values(value)?,
+            "--seeds" => options.sweep.seeds = parse_seeds(value)?,
+            "--param" => {
+                let (name, param) = value.split_once('=').ok_or(format!("invalid parameter '{}'", value))?;
+                if !param_names.contains(&name) {
+                    return Err(format!("the reiter model has no parameter '{}'", name));
+                }
+                options.sweep.params.push((name.to_string(), parse_value(param)?));
+            }
+            "--stop" => {
+                let conditions = stop_conditions.get_or_insert_with(StopConditions::default);
+                let (name, limit) = value.split_once('=').ok_or(format!("invalid stop condition '{}'", value))?;
+                if !conditions.set_by_name(name, parse_value(limit)?) {
+                    return Err(format!("unknown stop condition '{}'", name));
+                }
+            }
+            "--max-iterations" => options.sweep.max_iterations = parse_value(value)?,
+            "--tile-size" => options.sweep.tile_size = parse_value(value)?,
+            "--csv" => options.csv = PathBuf::from(value),
+            "--sheet" => options.sheet = PathBuf::from(value),
+            _ => return Err(format!("unknown option {}", arg)),
+        }
+    }
+    if let Some(conditions) = stop_conditions {
+        options.sweep.stop_conditions = conditions;
+    }
+    if options.sweep.width == 0 || options.sweep.height == 0 || options.sweep.tile_size == 0 {
+        return Err("sizes must be larger than zero".to_string());
+    }
+    return Ok(options);
+}
+
+fn parse_value<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    return value.parse().map_err(|_| format!("invalid value '{}'", value));
+}
+
+/// Parse a list of values such as 0.3,0.4 or evenly spaced values such as 0.3:0.9:7
+fn parse_values(value: &str) -> Result<Vec<f64>, String> {
+    let parts: Vec<&str> = value.split(':').collect();
+    if parts.len() == 3 {
+        let count: usize = parse_value(parts[2])?;
+        if count == 0 {
+            return Err(format!("invalid range '{}'", value));
+        }
+        return Ok(batch::linspace(parse_value(parts[0])?, parse_value(parts[1])?, count));
+    }
+    return value.split(',').map(parse_value).collect();
+}
+
+/// Parse a list of seeds such as 1,5 or a range of seeds such as 1:10
+fn parse_seeds(value: &str) -> Result<Vec<u64>, String> {
+    if let Some((first, last)) = value.split_once(':') {
+        let (first, last): (u64, u64) = (parse_value(first)?, parse_value(last)?);
+        if first > last {
+            return Err(format!("invalid range '{}'", value));
+        }
+        return Ok((first..last + 1).collect());
+    }
+    return value.split(',').map(parse_value).collect();
+}
+
+/// Run the sweep and write the measurements and contact sheet
+fn run(options: &Options) -> Result<(), String> {
+    let sweep = &options.sweep;
+    println!("Simulating {} runs", sweep.runs().len());
+    let results = sweep.run();
+
+    let file = File::create(&options.csv).map_err(|err| format!("could not create {}: {}", options.csv.display(), err))?;
+    let mut writer = BufWriter::new(file);
+    batch::write_csv(&results, &mut writer).map_err(|err| err.to_string())?;
+    writer.flush().map_err(|err| err.to_string())?;
+
+    let sheet = batch::render_contact_sheet(&results, sweep.tile_size, sweep.columns()).map_err(|err| err.to_string())?;
+    return std::fs::write(&options.sheet, sheet).map_err(|err| format!("could not create {}: {}", options.sheet.display(), err));
+}
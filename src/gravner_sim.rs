@@ -0,0 +1,517 @@
+use super::growth_model::GrowthModel;
+use super::sim::get_neighbours;
+use oorandom::Rand64;
+use serde::{Deserialize, Serialize};
+
+/// Name of the model, see `growth_model::create_model`
+pub const MODEL_NAME: &str = "gravner";
+static PARAM_NAMES: [&str; 8] = ["rho", "beta", "alpha", "theta", "kappa", "mu", "gamma", "sigma"];
+static RAND_SEED: u64 = 34917983469832;
+/// Largest water level reported for cells which are not attached, which
+/// can hold more mass than 1.0 before they attach
+const MAX_UNATTACHED_WATER: f64 = 1.0 - f64::EPSILON;
+
+/// Represents a single hexagonal cell of the Gravner-Griffeath model
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct GravnerCell {
+    /// Is this cell part of the crystal?
+    attached: bool,
+    /// Quasi-liquid mass at the crystal boundary
+    boundary_mass: f64,
+    /// Ice mass
+    crystal_mass: f64,
+    /// Vapor mass
+    diffusive_mass: f64,
+}
+
+/// Full state of a simulation, see `GrowthModel::save_state`
+#[derive(Serialize, Deserialize)]
+struct GravnerState {
+    current: Vec<GravnerCell>,
+    next: Vec<GravnerCell>,
+    // Parameter values in the order of `PARAM_NAMES`
+    params: Vec<f64>,
+    rand_state: (u128, u128),
+    seed: u64,
+    iteration_count: usize,
+}
+
+/// Represents a Snowflake Simulation based on the mesoscopic
+/// Gravner-Griffeath model,
+/// see https://www.math.ucdavis.edu/~gravner/papers/h3l.pdf for
+/// more details.
+///
+/// Unlike Reiters model, each cell keeps track of three separate
+/// masses, which allows for faceted and sectored plate growth.
+/// The grid uses the same padding and hexagonal layout as
+/// `sim::SnowflakeSim`.
+#[derive(Debug)]
+pub struct GravnerSim {
+    // Simulation state
+    pub current: Vec<GravnerCell>,
+    next: Vec<GravnerCell>,
+    pub width: usize,
+    pub height: usize,
+    // Real width and height (the array is padded)
+    rwidth: usize,
+    rheight: usize,
+
+    // Random number generator for the noise step
+    rand: Rand64,
+
+    // Simulation parameters
+    /// Rho
+    pub vapor_density: f64,
+    /// Beta
+    pub attachment_threshold: f64,
+    /// Alpha
+    pub knife_edge_threshold: f64,
+    /// Theta
+    pub concave_vapor_threshold: f64,
+    /// Kappa
+    pub freezing_fraction: f64,
+    /// Mu
+    pub boundary_melting: f64,
+    /// Gamma
+    pub crystal_melting: f64,
+    /// Sigma
+    pub noise: f64,
+    pub seed: u64,
+    pub iteration_count: usize,
+}
+
+impl GravnerSim {
+    /// Create a new simulation with the default parameters
+    /// from the Gravner-Griffeath paper, which produce a sectored plate.
+    pub fn new(width: usize, height: usize) -> GravnerSim {
+        let rho = 0.635;
+        let cell = GravnerCell {
+            attached: false,
+            boundary_mass: 0.0,
+            crystal_mass: 0.0,
+            diffusive_mass: rho,
+        };
+        let mut sim = GravnerSim {
+            current: vec![cell; (width + 2) * (height + 2)],
+            next: vec![cell; (width + 2) * (height + 2)],
+            width,
+            height,
+            rwidth: width + 2,
+            rheight: height + 2,
+            rand: Rand64::new(0),
+            vapor_density: rho,
+            attachment_threshold: 1.6,
+            knife_edge_threshold: 0.4,
+            concave_vapor_threshold: 0.025,
+            freezing_fraction: 0.005,
+            boundary_melting: 0.015,
+            crystal_melting: 0.0005,
+            noise: 0.0,
+            seed: 0,
+            iteration_count: 0,
+        };
+        sim.set_random_seed(RAND_SEED);
+        return sim;
+    }
+
+    /// Set the water level of a cell. A value of 1.0 or more attaches the
+    /// cell to the crystal, which is useful for initial setup of the
+    /// seed crystal. Lower values set the vapor mass of the cell.
+    pub fn set_water(&mut self, mut x: usize, mut y: usize, val: f64) {
+        // Adjust for padding manually
+        x += 1;
+        y += 1;
+        let cell = &mut self.current[y * self.rwidth + x];
+        if val >= 1.0 {
+            cell.attached = true;
+            cell.boundary_mass = 0.0;
+            cell.crystal_mass = val;
+            cell.diffusive_mass = 0.0;
+        } else {
+            cell.attached = false;
+            cell.boundary_mass = 0.0;
+            cell.crystal_mass = 0.0;
+            cell.diffusive_mass = val;
+        }
+    }
+
+    /// Get the water level of a cell. Attached cells report their
+    /// crystal mass (at least 1.0), other cells report the mass which
+    /// has started to freeze, kept below 1.0 so they never count as frozen.
+    pub fn get_water(&self, mut x: usize, mut y: usize) -> f64 {
+        // Adjust for padding manually
+        x += 1;
+        y += 1;
+        let cell = self.current[y * self.rwidth + x];
+        if cell.attached {
+            return cell.crystal_mass.max(1.0);
+        } else {
+            return (cell.boundary_mass + cell.crystal_mass).min(MAX_UNATTACHED_WATER);
+        }
+    }
+
+    pub fn set_random_seed(&mut self, seed: u64) {
+        self.rand = Rand64::new(seed as u128);
+        self.seed = seed;
+    }
+
+    /// Fill all non-crystal cells with the starting vapor density
+    pub fn fill_starting_vapor(&mut self) {
+        for i in 0..self.current.len() {
+            if !self.current[i].attached {
+                self.current[i].diffusive_mass = self.vapor_density;
+                self.next[i].diffusive_mass = self.vapor_density;
+            }
+        }
+    }
+
+    /// Step the Gravner-Griffeath simulation one iteration.
+    pub fn step(&mut self) {
+        self.step_diffusion();
+        self.step_freezing();
+        self.step_attachment();
+        self.step_melting();
+        if self.noise > 0.0 {
+            self.step_noise();
+        }
+
+        // Loop over edge cells and introduce vapor to the system
+        for y in 1..self.height + 1 {
+            self.set_edge_vapor(1, y);
+            self.set_edge_vapor(self.width, y);
+        }
+        for x in 1..self.width + 1 {
+            self.set_edge_vapor(x, 1);
+            self.set_edge_vapor(x, self.height);
+        }
+
+        self.iteration_count += 1;
+    }
+
+    fn set_edge_vapor(&mut self, x: usize, y: usize) {
+        let cell = &mut self.current[y * self.rwidth + x];
+        if !cell.attached {
+            cell.diffusive_mass = self.vapor_density;
+        }
+    }
+
+    /// Count the attached neighbours of a cell
+    fn count_attached_neighbours(&self, x: usize, y: usize) -> usize {
+        let mut count = 0;
+        for (nx, ny) in get_neighbours(x as isize, y as isize) {
+            if self.current[(ny as usize) * self.rwidth + nx as usize].attached {
+                count += 1;
+            }
+        }
+        return count;
+    }
+
+    /// Diffuse the vapor mass. Attached neighbours reflect the vapor
+    /// back into the cell.
+    fn step_diffusion(&mut self) {
+        for y in 1..self.height + 1 {
+            for x in 1..self.width + 1 {
+                let cell = self.current[y * self.rwidth + x];
+                let mut next_cell = cell;
+                if !cell.attached {
+                    let mut vapor_sum = cell.diffusive_mass;
+                    for (nx, ny) in get_neighbours(x as isize, y as isize) {
+                        let neighbour = self.current[(ny as usize) * self.rwidth + nx as usize];
+                        if neighbour.attached {
+                            vapor_sum += cell.diffusive_mass;
+                        } else {
+                            vapor_sum += neighbour.diffusive_mass;
+                        }
+                    }
+                    next_cell.diffusive_mass = vapor_sum / 7.0;
+                }
+                self.next[y * self.rwidth + x] = next_cell;
+            }
+        }
+        std::mem::swap(&mut self.current, &mut self.next);
+    }
+
+    /// Freeze part of the vapor in boundary cells
+    fn step_freezing(&mut self) {
+        for y in 1..self.height + 1 {
+            for x in 1..self.width + 1 {
+                if self.current[y * self.rwidth + x].attached || self.count_attached_neighbours(x, y) == 0 {
+                    continue;
+                }
+                let cell = &mut self.current[y * self.rwidth + x];
+                cell.boundary_mass += (1.0 - self.freezing_fraction) * cell.diffusive_mass;
+                cell.crystal_mass += self.freezing_fraction * cell.diffusive_mass;
+                cell.diffusive_mass = 0.0;
+            }
+        }
+    }
+
+    /// Attach boundary cells to the crystal depending on the amount of
+    /// attached neighbours and the local boundary and vapor mass
+    fn step_attachment(&mut self) {
+        for y in 1..self.height + 1 {
+            for x in 1..self.width + 1 {
+                let cell = self.current[y * self.rwidth + x];
+                let mut next_cell = cell;
+                let attached_count = self.count_attached_neighbours(x, y);
+                if !cell.attached && attached_count > 0 {
+                    let attach = match attached_count {
+                        1 | 2 => cell.boundary_mass >= self.attachment_threshold,
+                        3 => {
+                            let mut vapor_sum = cell.diffusive_mass;
+                            for (nx, ny) in get_neighbours(x as isize, y as isize) {
+                                vapor_sum += self.current[(ny as usize) * self.rwidth + nx as usize].diffusive_mass;
+                            }
+                            cell.boundary_mass >= 1.0
+                                || (vapor_sum < self.concave_vapor_threshold
+                                    && cell.boundary_mass >= self.knife_edge_threshold)
+                        }
+                        _ => true,
+                    };
+                    if attach {
+                        next_cell.attached = true;
+                        next_cell.crystal_mass = cell.boundary_mass + cell.crystal_mass;
+                        next_cell.boundary_mass = 0.0;
+                    }
+                }
+                self.next[y * self.rwidth + x] = next_cell;
+            }
+        }
+        std::mem::swap(&mut self.current, &mut self.next);
+    }
+
+    /// Melt part of the boundary and crystal mass of boundary cells
+    /// back into vapor
+    fn step_melting(&mut self) {
+        for y in 1..self.height + 1 {
+            for x in 1..self.width + 1 {
+                if self.current[y * self.rwidth + x].attached || self.count_attached_neighbours(x, y) == 0 {
+                    continue;
+                }
+                let cell = &mut self.current[y * self.rwidth + x];
+                let melted_boundary = self.boundary_melting * cell.boundary_mass;
+                let melted_crystal = self.crystal_melting * cell.crystal_mass;
+                cell.boundary_mass -= melted_boundary;
+                cell.crystal_mass -= melted_crystal;
+                cell.diffusive_mass += melted_boundary + melted_crystal;
+            }
+        }
+    }
+
+    /// Perturb the vapor mass of every cell by a factor of (1 +- sigma)
+    fn step_noise(&mut self) {
+        for y in 1..self.height + 1 {
+            for x in 1..self.width + 1 {
+                let sign = if self.rand.rand_u64() & 1 == 0 { 1.0 } else { -1.0 };
+                self.current[y * self.rwidth + x].diffusive_mass *= 1.0 + sign * self.noise;
+            }
+        }
+    }
+}
+
+impl GrowthModel for GravnerSim {
+    fn model_name(&self) -> &'static str {
+        return MODEL_NAME;
+    }
+
+    fn width(&self) -> usize {
+        return self.width;
+    }
+
+    fn height(&self) -> usize {
+        return self.height;
+    }
+
+    fn iteration_count(&self) -> usize {
+        return self.iteration_count;
+    }
+
+    fn step(&mut self) {
+        GravnerSim::step(self);
+    }
+
+    fn get_water(&self, x: usize, y: usize) -> f64 {
+        return GravnerSim::get_water(self, x, y);
+    }
+
+    fn set_water(&mut self, x: usize, y: usize, val: f64) {
+        GravnerSim::set_water(self, x, y, val);
+    }
+
+    fn is_frozen(&self, x: usize, y: usize) -> bool {
+        return self.current[(y + 1) * self.rwidth + x + 1].attached;
+    }
+
+    fn start_water(&self, x: usize, y: usize) -> f64 {
+        let cell = self.current[(y + 1) * self.rwidth + x + 1];
+        return if cell.attached { cell.crystal_mass } else { cell.diffusive_mass };
+    }
+
+    fn param_names(&self) -> &'static [&'static str] {
+        return &PARAM_NAMES;
+    }
+
+    fn get_param(&self, name: &str) -> Option<f64> {
+        return match name {
+            "rho" => Some(self.vapor_density),
+            "beta" => Some(self.attachment_threshold),
+            "alpha" => Some(self.knife_edge_threshold),
+            "theta" => Some(self.concave_vapor_threshold),
+            "kappa" => Some(self.freezing_fraction),
+            "mu" => Some(self.boundary_melting),
+            "gamma" => Some(self.crystal_melting),
+            "sigma" => Some(self.noise),
+            _ => None,
+        };
+    }
+
+    fn set_param(&mut self, name: &str, value: f64) -> bool {
+        match name {
+            "rho" => {
+                self.vapor_density = value;
+                if self.iteration_count == 0 {
+                    // We need to update the starting vapor
+                    self.fill_starting_vapor();
+                }
+            }
+            "beta" => self.attachment_threshold = value,
+            "alpha" => self.knife_edge_threshold = value,
+            "theta" => self.concave_vapor_threshold = value,
+            "kappa" => self.freezing_fraction = value,
+            "mu" => self.boundary_melting = value,
+            "gamma" => self.crystal_melting = value,
+            "sigma" => self.noise = value,
+            _ => return false,
+        }
+        return true;
+    }
+
+    fn seed(&self) -> u64 {
+        return self.seed;
+    }
+
+    fn set_random_seed(&mut self, seed: u64) {
+        GravnerSim::set_random_seed(self, seed);
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        let state = GravnerState {
+            current: self.current.clone(),
+            next: self.next.clone(),
+            params: PARAM_NAMES.iter().map(|name| self.get_param(name).unwrap()).collect(),
+            rand_state: self.rand.state(),
+            seed: self.seed,
+            iteration_count: self.iteration_count,
+        };
+        return bincode::serialize(&state).unwrap();
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> bool {
+        let state: GravnerState = match bincode::deserialize(state) {
+            Ok(state) => state,
+            Err(_) => return false,
+        };
+        if state.current.len() != self.current.len() || state.next.len() != self.next.len()
+            || state.params.len() != PARAM_NAMES.len() {
+            return false;
+        }
+        // Parameters first, as they can refill the cells
+        for (name, value) in PARAM_NAMES.iter().zip(&state.params) {
+            self.set_param(name, *value);
+        }
+        self.rand = Rand64::from_state(state.rand_state);
+        self.seed = state.seed;
+        self.current = state.current;
+        self.next = state.next;
+        self.iteration_count = state.iteration_count;
+        return true;
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        return self;
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        return self;
+    }
+}
+
+// Implement display trait to allow for printing of the simulation
+impl std::fmt::Display for GravnerSim {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for y in 0..self.rheight {
+            for x in 0..self.rwidth {
+                let cell = self.current[y * self.rwidth + x];
+                let mass = if cell.attached { cell.crystal_mass } else { cell.diffusive_mass };
+                fmt.write_str(&format!("{:.2} ", mass))?;
+            }
+            fmt.write_str("\n")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{morphology, symmetry_score};
+
+    static GRID_WIDTH: usize = 100;
+    static GRID_HEIGHT: usize = 100;
+    static ITERATIONS: usize = 500;
+
+    #[test]
+    fn test_gravner_sim() {
+        let mut simulation = GravnerSim::new(GRID_WIDTH, GRID_HEIGHT);
+        simulation.set_water(GRID_WIDTH / 2 - 1, GRID_HEIGHT / 2 - 1, 1.0);
+
+        for _ in 0..ITERATIONS {
+            simulation.step();
+        }
+
+        // The crystal should have grown from the seed, without
+        // reaching the edge of the grid
+        let frozen = simulation.current.iter().filter(|cell| cell.attached).count();
+        assert!(frozen > 1);
+        assert!(simulation.get_water(0, GRID_HEIGHT / 2) < 1.0);
+        assert!(simulation.current.iter().all(|cell| cell.diffusive_mass >= 0.0));
+        // Cells holding more mass than 1.0 only count as frozen once attached
+        simulation.current[simulation.rwidth + 1].boundary_mass = 1.5;
+        assert!(simulation.get_water(0, 0) < 1.0);
+        assert!(!GrowthModel::is_frozen(&simulation, 0, 0));
+    }
+
+    /// Grow a crystal from a single cell in the center of the grid
+    fn grow(size: usize, iterations: usize, params: [f64; 7]) -> GravnerSim {
+        let mut simulation = GravnerSim::new(size, size);
+        for (name, value) in PARAM_NAMES.iter().zip(params) {
+            GrowthModel::set_param(&mut simulation, name, value);
+        }
+        simulation.set_water(size / 2, size / 2, 1.0);
+        for _ in 0..iterations {
+            simulation.step();
+        }
+        return simulation;
+    }
+
+    #[test]
+    fn test_gravner_morphology() {
+        // Plate parameters grow a filled hexagon
+        let simulation = grow(60, 600, [0.8, 2.6, 0.004, 0.001, 0.05, 0.015, 0.0001]);
+        let score = symmetry_score::measure(&simulation, (30, 30));
+        let shape = morphology::measure(&simulation, (30, 30));
+        assert_eq!(score.index, 1.0);
+        assert_eq!(shape.holes, 0);
+        let radius = shape.max_radius.round() as usize;
+        assert!(radius > 10);
+        assert_eq!(shape.area, 3 * radius * (radius + 1) + 1);
+
+        // Dendrite parameters grow six branches with side branches
+        let simulation = grow(132, 1300, [0.5, 1.4, 0.1, 0.005, 0.001, 0.001, 0.0005]);
+        let shape = morphology::measure(&simulation, (66, 66));
+        assert_eq!(shape.primary_branches, 6);
+        assert!(shape.secondary_branches > 0);
+        assert_eq!(shape.holes, 0);
+    }
+}
+
@@ -0,0 +1,108 @@
+use super::gravner_sim;
+use super::sim;
+use std::any::Any;
+
+/// A hexagonal growth automaton which can be driven by a
+/// `SnowflakeSimContext`. All models use the same padded grid and
+/// hexagonal layout, see `sim::get_neighbours`.
+pub trait GrowthModel {
+    /// Get the name of the model, used to recreate it with `create_model`
+    fn model_name(&self) -> &'static str;
+
+    /// Get the width of the simulation grid, excluding padding
+    fn width(&self) -> usize;
+
+    /// Get the height of the simulation grid, excluding padding
+    fn height(&self) -> usize;
+
+    /// Get the amount of iterations which have been simulated
+    fn iteration_count(&self) -> usize;
+
+    /// Step the simulation one iteration
+    fn step(&mut self);
+
+    /// Get the water level of a cell. Cells with a water level
+    /// of 1.0 or more are considered frozen.
+    fn get_water(&self, x: usize, y: usize) -> f64;
+
+    /// Set the water level of a cell. A water level of 1.0 or more
+    /// freezes the cell, which is used to place the seed crystal.
+    fn set_water(&mut self, x: usize, y: usize, val: f64);
+
+    /// Get the names of all parameters of the model
+    fn param_names(&self) -> &'static [&'static str];
+
+    /// Get a parameter by name, or `None` if the model
+    /// has no such parameter
+    fn get_param(&self, name: &str) -> Option<f64>;
+
+    /// Set a parameter by name. Returns false if the model
+    /// has no such parameter.
+    fn set_param(&mut self, name: &str, value: f64) -> bool;
+
+    /// Get the random seed of the simulation
+    fn seed(&self) -> u64;
+
+    /// Set the random seed of the simulation
+    fn set_random_seed(&mut self, seed: u64);
+
+    /// Save the full state of the simulation, including both cell buffers,
+    /// the parameters, the random state and the iteration count
+    fn save_state(&self) -> Vec<u8>;
+
+    /// Restore a state saved by `save_state` of a model of the same kind
+    /// and size. Returns false if the state does not fit the model.
+    fn load_state(&mut self, state: &[u8]) -> bool;
+
+    /// Get the model as `Any`, to access model specific functionality
+    fn as_any(&self) -> &dyn Any;
+
+    /// Get the model as mutable `Any`, to access model specific functionality
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    /// Is the cell part of the crystal?
+    fn is_frozen(&self, x: usize, y: usize) -> bool {
+        return self.get_water(x, y) >= 1.0;
+    }
+
+    /// Get the water level of a cell as it would be given to `set_water`,
+    /// which recreates the cell before the first iteration. Models which
+    /// report only part of the water of a cell in `get_water` override this.
+    fn start_water(&self, x: usize, y: usize) -> f64 {
+        return self.get_water(x, y);
+    }
+}
+
+/// Create a growth model by name with its default parameters.
+/// Returns `None` if the name is unknown.
+pub fn create_model(name: &str, width: usize, height: usize) -> Option<Box<dyn GrowthModel>> {
+    return match name {
+        sim::MODEL_NAME => Some(Box::new(sim::SnowflakeSim::new(width, height, 1.0, 0.4, 0.0001))),
+        gravner_sim::MODEL_NAME => Some(Box::new(gravner_sim::GravnerSim::new(width, height))),
+        _ => None,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_params() {
+        for name in [sim::MODEL_NAME, gravner_sim::MODEL_NAME] {
+            let mut model = create_model(name, 20, 20).unwrap();
+            assert_eq!(model.model_name(), name);
+            for param in model.param_names() {
+                assert!(model.set_param(param, 0.5));
+                assert_eq!(model.get_param(param), Some(0.5));
+            }
+            assert!(!model.set_param("nonexistent", 0.5));
+            assert_eq!(model.get_param("nonexistent"), None);
+
+            model.set_water(10, 10, 1.0);
+            assert!(model.is_frozen(10, 10));
+            model.step();
+            assert_eq!(model.iteration_count(), 1);
+        }
+    }
+}
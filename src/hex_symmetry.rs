@@ -0,0 +1,155 @@
+/// Cube coordinates of a hexagonal cell, where x + y + z = 0
+pub type Cube = (isize, isize, isize);
+
+/// Convert padded offset coordinates to cube coordinates.
+/// The offset layout is the same as in `sim::get_neighbours`.
+pub fn offset_to_cube(x: isize, y: isize) -> Cube {
+    let q = x - (y + (y & 1)) / 2;
+    return (q, -q - y, y);
+}
+
+/// Convert cube coordinates to padded offset coordinates
+pub fn cube_to_offset(cube: Cube) -> (isize, isize) {
+    let y = cube.2;
+    return (cube.0 + (y + (y & 1)) / 2, y);
+}
+
+/// Get the hexagonal distance of a cube coordinate from the origin
+pub fn cube_length(cube: Cube) -> isize {
+    return (cube.0.abs() + cube.1.abs() + cube.2.abs()) / 2;
+}
+
+/// Apply one of the 12 (D6) symmetry operations around the origin.
+/// Operations 0-5 are rotations by a multiple of 60 degrees,
+/// operations 6-11 are reflections followed by a rotation.
+pub fn transform(cube: Cube, op: usize) -> Cube {
+    let mut cube = if op >= 6 { (cube.0, cube.2, cube.1) } else { cube };
+    for _ in 0..op % 6 {
+        cube = (-cube.2, -cube.0, -cube.1);
+    }
+    return cube;
+}
+
+/// Get the representative of a cube coordinate in the fundamental wedge,
+/// which is the same for all 12 symmetric images of the coordinate
+pub fn canonical(cube: Cube) -> Cube {
+    return (0..12).map(|op| transform(cube, op)).max().unwrap();
+}
+
+/// The part of a padded grid which is simulated in symmetric mode.
+/// This is the largest hexagon around the center which fits in the grid,
+/// where every cell is represented by a cell in the fundamental wedge.
+#[derive(Clone, Debug)]
+pub struct SymmetryDomain {
+    /// Maps every padded index to the index of its wedge representative.
+    /// Cells outside of the hexagon map to themselves.
+    pub map: Vec<usize>,
+    /// Wedge cells which are stepped
+    pub cells: Vec<usize>,
+    /// Padded indices of the cells every wedge cell represents,
+    /// including itself, in the order of `cells`
+    pub orbits: Vec<Vec<usize>>,
+    /// Wedge cells on the outermost ring of the hexagon
+    pub edge: Vec<usize>,
+    /// Padded position of the center cell
+    pub center: (usize, usize),
+}
+
+impl SymmetryDomain {
+    /// Create the symmetry domain of a padded grid
+    ///
+    /// * `rwidth`, `rheight` - size of the padded grid
+    /// * `cx`, `cy` - padded position of the center cell
+    pub fn new(rwidth: usize, rheight: usize, cx: usize, cy: usize) -> SymmetryDomain {
+        let center = offset_to_cube(cx as isize, cy as isize);
+        let relative = |x: usize, y: usize| {
+            let cube = offset_to_cube(x as isize, y as isize);
+            return (cube.0 - center.0, cube.1 - center.1, cube.2 - center.2);
+        };
+
+        // The hexagon radius is limited by the closest padding cell
+        let mut radius = isize::MAX;
+        for y in 0..rheight {
+            for x in 0..rwidth {
+                if x == 0 || y == 0 || x == rwidth - 1 || y == rheight - 1 {
+                    radius = radius.min(cube_length(relative(x, y)));
+                }
+            }
+        }
+
+        let mut domain = SymmetryDomain {
+            map: (0..rwidth * rheight).collect(),
+            cells: Vec::new(),
+            orbits: Vec::new(),
+            edge: Vec::new(),
+            center: (cx, cy),
+        };
+        for y in 0..rheight {
+            for x in 0..rwidth {
+                let cube = relative(x, y);
+                let distance = cube_length(cube);
+                if distance >= radius {
+                    continue;
+                }
+                let rep = canonical(cube);
+                let (rx, ry) = cube_to_offset((rep.0 + center.0, rep.1 + center.1, rep.2 + center.2));
+                let i = y * rwidth + x;
+                domain.map[i] = (ry as usize) * rwidth + rx as usize;
+                if domain.map[i] == i {
+                    domain.cells.push(i);
+                    if distance == radius - 1 {
+                        domain.edge.push(i);
+                    }
+                }
+            }
+        }
+
+        let mut orbit_of = vec![usize::MAX; rwidth * rheight];
+        for (k, &i) in domain.cells.iter().enumerate() {
+            orbit_of[i] = k;
+        }
+        domain.orbits = vec![Vec::new(); domain.cells.len()];
+        for (i, &rep) in domain.map.iter().enumerate() {
+            if orbit_of[rep] != usize::MAX {
+                domain.orbits[orbit_of[rep]].push(i);
+            }
+        }
+        return domain;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::sim::get_neighbours;
+
+    #[test]
+    fn test_symmetry_domain() {
+        // Neighbours on the offset grid must be neighbours in cube space
+        for y in 1..6 {
+            for x in 1..6 {
+                let cube = offset_to_cube(x, y);
+                assert_eq!(cube_to_offset(cube), (x, y));
+                for (nx, ny) in get_neighbours(x, y) {
+                    let n = offset_to_cube(nx, ny);
+                    assert_eq!(cube_length((n.0 - cube.0, n.1 - cube.1, n.2 - cube.2)), 1);
+                }
+            }
+        }
+
+        // Every wedge cell represents 12 cells, except those on the
+        // symmetry axes, so the wedge is roughly 1/12 of the hexagon
+        let domain = SymmetryDomain::new(102, 102, 51, 51);
+        let hexagon_size = domain.map.iter().enumerate().filter(|(i, rep)| *i != **rep).count()
+            + domain.cells.len();
+        assert!(domain.cells.len() * 10 < hexagon_size);
+        for &i in &domain.cells {
+            assert_eq!(domain.map[i], i);
+        }
+        for (&i, orbit) in domain.cells.iter().zip(&domain.orbits) {
+            assert!(orbit.contains(&i) && orbit.len() <= 12);
+            assert!(orbit.iter().all(|&j| domain.map[j] == i));
+        }
+        assert_eq!(domain.orbits.iter().map(|orbit| orbit.len()).sum::<usize>(), hexagon_size);
+    }
+}
@@ -0,0 +1,169 @@
+use super::growth_model::GrowthModel;
+use super::{hex_corner, hex_pixel_coord, COLOR_CUTTOFF, HEX_SIZE};
+
+/// Settings for rendering a simulation to an image
+#[derive(Clone, Debug)]
+pub struct ImageOptions {
+    /// Width of the image in pixels
+    pub width: u32,
+    /// Height of the image in pixels
+    pub height: u32,
+    /// RGBA color which the water level is multiplied with,
+    /// between 0.0 and 1.0
+    pub tint: [f32; 4],
+    /// Should cells without color be transparent?
+    pub transparent_background: bool,
+}
+
+impl Default for ImageOptions {
+    fn default() -> ImageOptions {
+        return ImageOptions {
+            width: 1000,
+            height: 1000,
+            // Same color as the web display
+            tint: [0.5, 0.82, 0.96, 1.0],
+            transparent_background: false,
+        };
+    }
+}
+
+/// Rasterize the hexagonal grid of a simulation into an RGBA image,
+/// in the same way as the WebGL display. The grid is scaled to fit
+/// the image and centered.
+pub fn render_rgba(sim: &dyn GrowthModel, options: &ImageOptions) -> Vec<u8> {
+    let (width, height) = (options.width as usize, options.height as usize);
+    let background: [u8; 4] = if options.transparent_background { [0, 0, 0, 0] } else { [0, 0, 0, 255] };
+    let mut pixels: Vec<u8> = background.iter().cycle().take(width * height * 4).copied().collect();
+    if sim.width() == 0 || sim.height() == 0 {
+        return pixels;
+    }
+
+    // Find the bounds of the grid to fit it into the image
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+    for y in 0..sim.height() {
+        for x in [0, sim.width() - 1] {
+            let (px, py) = hex_pixel_coord(x, y, HEX_SIZE);
+            for i in 0..6 {
+                let (cx, cy) = hex_corner(px, py, HEX_SIZE, i);
+                min_x = min_x.min(cx);
+                min_y = min_y.min(cy);
+                max_x = max_x.max(cx);
+                max_y = max_y.max(cy);
+            }
+        }
+    }
+    let scale = (width as f32 / (max_x - min_x)).min(height as f32 / (max_y - min_y));
+    let offset_x = (width as f32 - (max_x - min_x) * scale) / 2.0;
+    let offset_y = (height as f32 - (max_y - min_y) * scale) / 2.0;
+    // The display has y pointing up, images have y pointing down
+    let to_image = |(px, py): (f32, f32)| {
+        return ((px - min_x) * scale + offset_x, height as f32 - ((py - min_y) * scale + offset_y));
+    };
+
+    for y in 0..sim.height() {
+        for x in 0..sim.width() {
+            let water = sim.get_water(x, y) as f32;
+            let color = if water < COLOR_CUTTOFF { 0.0 } else { water };
+            if color == 0.0 && options.transparent_background {
+                continue;
+            }
+            let rgba = [
+                to_color_byte(color * options.tint[0]),
+                to_color_byte(color * options.tint[1]),
+                to_color_byte(color * options.tint[2]),
+                to_color_byte(options.tint[3]),
+            ];
+
+            let (px, py) = hex_pixel_coord(x, y, HEX_SIZE);
+            let mut corners = [(0.0, 0.0); 6];
+            for (i, corner) in corners.iter_mut().enumerate() {
+                *corner = to_image(hex_corner(px, py, HEX_SIZE, i));
+            }
+            fill_convex_polygon(&mut pixels, width, height, &corners, rgba);
+        }
+    }
+    return pixels;
+}
+
+/// Render a simulation and encode it as a PNG image.
+/// Fails if the width or height of the image is zero.
+pub fn render_png(sim: &dyn GrowthModel, options: &ImageOptions) -> Result<Vec<u8>, png::EncodingError> {
+    let pixels = render_rgba(sim, options);
+    return encode_png(&pixels, options.width, options.height);
+}
+
+/// Encode RGBA pixels as a PNG image. Fails if the width or height
+/// is zero, or if the pixels do not match the size.
+pub fn encode_png(pixels: &[u8], width: u32, height: u32) -> Result<Vec<u8>, png::EncodingError> {
+    let mut bytes = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(pixels)?;
+    }
+    return Ok(bytes);
+}
+
+/// Map a color channel between 0.0 and 1.0 to a byte
+fn to_color_byte(value: f32) -> u8 {
+    return (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+}
+
+/// Fill the pixels whose centers lie within a convex polygon
+fn fill_convex_polygon(pixels: &mut [u8], width: usize, height: usize, corners: &[(f32, f32)], rgba: [u8; 4]) {
+    let min_x = corners.iter().map(|c| c.0).fold(f32::MAX, f32::min).floor().max(0.0) as usize;
+    let max_x = corners.iter().map(|c| c.0).fold(f32::MIN, f32::max).ceil().min(width as f32) as usize;
+    let min_y = corners.iter().map(|c| c.1).fold(f32::MAX, f32::min).floor().max(0.0) as usize;
+    let max_y = corners.iter().map(|c| c.1).fold(f32::MIN, f32::max).ceil().min(height as f32) as usize;
+    for iy in min_y..max_y {
+        for ix in min_x..max_x {
+            let (sx, sy) = (ix as f32 + 0.5, iy as f32 + 0.5);
+            // The pixel is inside if it is on the same side of every edge.
+            // Pixels exactly on an edge are included to avoid gaps.
+            let mut positive = false;
+            let mut negative = false;
+            for i in 0..corners.len() {
+                let (ax, ay) = corners[i];
+                let (bx, by) = corners[(i + 1) % corners.len()];
+                let cross = (bx - ax) * (sy - ay) - (by - ay) * (sx - ax);
+                positive |= cross > 0.0;
+                negative |= cross < 0.0;
+            }
+            if !(positive && negative) {
+                let i = (iy * width + ix) * 4;
+                pixels[i..i + 4].copy_from_slice(&rgba);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::sim;
+
+    #[test]
+    fn test_render_png() {
+        let mut simulation = sim::SnowflakeSim::new(20, 20, 1.0, 0.4, 0.0001);
+        simulation.set_water(10, 10, 1.0);
+        let options = ImageOptions { width: 64, height: 48, tint: [1.0, 0.5, 0.0, 1.0], transparent_background: true };
+
+        let pixels = render_rgba(&simulation, &options);
+        assert_eq!(pixels.len(), 64 * 48 * 4);
+        // Only the seed crystal is above the color cutoff, and is drawn
+        // with the tint color. The y axis is flipped like the display.
+        let seed = (22 * 64 + 32) * 4;
+        assert_eq!(&pixels[seed..seed + 4], &[255, 128, 0, 255]);
+        assert_eq!(&pixels[0..4], &[0, 0, 0, 0]);
+        let opaque = pixels.chunks(4).filter(|pixel| pixel[3] != 0).count();
+        assert!(opaque > 0 && opaque < 64 * 48 / 50);
+
+        let png = render_png(&simulation, &options).unwrap();
+        assert_eq!(&png[0..8], &[137, 80, 78, 71, 13, 10, 26, 10]);
+
+        // Empty images can not be encoded
+        assert!(render_png(&simulation, &ImageOptions { width: 0, height: 0, ..options }).is_err());
+    }
+}
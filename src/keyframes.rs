@@ -0,0 +1,116 @@
+use super::growth_model::GrowthModel;
+
+use flate2::write::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use std::io::Write;
+
+/// Default amount of iterations between keyframes
+pub const DEFAULT_KEYFRAME_INTERVAL: usize = 250;
+
+/// Compressed full-state checkpoints of a simulation, taken at a
+/// regular interval of iterations. Used to seek during playback
+/// without stepping from the start.
+pub struct Keyframes {
+    interval: usize,
+    // Iteration and compressed state, sorted by iteration
+    frames: Vec<(usize, Vec<u8>)>,
+}
+
+impl Default for Keyframes {
+    fn default() -> Keyframes {
+        return Keyframes::new(DEFAULT_KEYFRAME_INTERVAL);
+    }
+}
+
+impl Keyframes {
+    /// Create an empty set of keyframes
+    ///
+    /// * `interval` - iterations between keyframes, 0 only keeps the first one
+    pub fn new(interval: usize) -> Keyframes {
+        return Keyframes { interval, frames: Vec::new() };
+    }
+
+    /// Change the interval of future keyframes. Existing keyframes are kept.
+    pub fn set_interval(&mut self, interval: usize) {
+        self.interval = interval;
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// Capture a keyframe if the simulation is at a keyframe iteration.
+    /// Iteration 0 is always captured, so there is always a keyframe to seek from.
+    pub fn capture(&mut self, sim: &dyn GrowthModel) {
+        let iteration = sim.iteration_count();
+        if iteration != 0 && (self.interval == 0 || !iteration.is_multiple_of(self.interval)) {
+            return;
+        }
+        // Replays are deterministic, so an existing keyframe is still valid
+        if let Err(index) = self.frames.binary_search_by_key(&iteration, |(i, _)| *i) {
+            self.frames.insert(index, (iteration, compress(&sim.save_state())));
+        }
+    }
+
+    /// Get the iteration of the latest keyframe at or before `iteration`
+    pub fn latest(&self, iteration: usize) -> Option<usize> {
+        let index = self.frames.partition_point(|(i, _)| *i <= iteration);
+        return index.checked_sub(1).map(|index| self.frames[index].0);
+    }
+
+    /// Restore the latest keyframe at or before `iteration`.
+    /// Returns the restored iteration, or `None` if there is no such keyframe.
+    pub fn restore(&self, sim: &mut dyn GrowthModel, iteration: usize) -> Option<usize> {
+        let index = self.frames.partition_point(|(i, _)| *i <= iteration).checked_sub(1)?;
+        let (keyframe, data) = &self.frames[index];
+        let mut decoder = ZlibDecoder::new(Vec::new());
+        // Keyframes are only created by `capture`, so they always decode
+        decoder.write_all(data).unwrap();
+        if !sim.load_state(&decoder.finish().unwrap()) {
+            return None;
+        }
+        return Some(*keyframe);
+    }
+}
+
+/// Compress a state, favouring speed as keyframes are captured during playback
+fn compress(state: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::fast());
+    encoder.write_all(state).unwrap();
+    return encoder.finish().unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::growth_model::create_model;
+    use super::super::{gravner_sim, sim};
+
+    #[test]
+    fn test_keyframe_restore() {
+        for name in [sim::MODEL_NAME, gravner_sim::MODEL_NAME] {
+            let mut model = create_model(name, 30, 30).unwrap();
+            // Randomization makes sure the random state is restored too
+            model.set_param(if name == sim::MODEL_NAME { "beta_rand" } else { "sigma" }, 0.2);
+            model.set_water(15, 15, 1.0);
+
+            let mut keyframes = Keyframes::new(10);
+            let mut states = Vec::new();
+            for _ in 0..40 {
+                keyframes.capture(model.as_ref());
+                states.push(model.save_state());
+                model.step();
+            }
+            assert_eq!(keyframes.latest(9), Some(0));
+            assert_eq!(keyframes.latest(25), Some(20));
+            assert_eq!(keyframes.latest(1000), Some(30));
+
+            // Restoring and stepping gives the same states as before
+            assert_eq!(keyframes.restore(model.as_mut(), 25), Some(20));
+            for state in &states[20..26] {
+                assert!(model.save_state() == *state);
+                model.step();
+            }
+        }
+    }
+}
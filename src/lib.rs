@@ -1,21 +1,43 @@
-#![feature(test)]
+#![allow(clippy::needless_return)]
 use wasm_bindgen::prelude::*;
-mod sim;
+mod active_region;
+pub mod batch;
+pub mod gravner_sim;
+pub mod growth_model;
+mod hex_symmetry;
+pub mod image_export;
+mod keyframes;
+pub mod mesh_export;
+pub mod morphology;
+pub mod noise;
+pub mod schedule;
+pub mod seed_crystal;
+mod rewind;
+pub mod sim;
 mod sim_history;
+pub mod snapshot;
+pub mod svg_export;
+pub mod symmetry_score;
+pub mod termination;
+
+use growth_model::GrowthModel;
+pub use sim_history::HistoryError;
+pub use snapshot::SnapshotError;
 
 /// Hexagon size for creating vertices. This should be
 /// 1.0 and then rescaled in the view, not here in the simulation
-static HEX_SIZE: f32 = 1.0;
+pub(crate) static HEX_SIZE: f32 = 1.0;
 /// At what water value should we start displaying color?
-static COLOR_CUTTOFF: f32 = 0.6;
+pub(crate) static COLOR_CUTTOFF: f32 = 0.6;
 
 #[wasm_bindgen]
 
 /// Represents the simulation context which exposes an interface of the
 /// simulation as well as helpers for rendering the simulation
 pub struct SnowflakeSimContext {
-    sim: sim::SnowflakeSim,
+    sim: Box<dyn GrowthModel>,
     sim_history: sim_history::SimStateHistory,
+    rewind: rewind::RewindBuffer,
     vertex_positions: Vec<f32>,
     vertex_colors: Vec<f32>,
     transparent_background: bool,
@@ -30,46 +52,175 @@ impl SnowflakeSimContext {
         beta: f64,
         gamma: f64,
     ) -> SnowflakeSimContext {
-        SnowflakeSimContext {
-            sim: sim::SnowflakeSim::new(width, height, alpha, beta, gamma),
-            vertex_positions: vec![0.0; width * height * 2 * 4 * 3],
-            vertex_colors: vec![0.0; width * height * 4 * 4 * 3],
-            sim_history: sim_history::SimStateHistory::new(),
-            transparent_background: false
-        }
+        let sim = sim::SnowflakeSim::new(width, height, alpha, beta, gamma);
+        return SnowflakeSimContext::from_model(Box::new(sim));
+    }
+
+    /// Create a simulation context using the Gravner-Griffeath model
+    /// with its default parameters
+    pub fn new_gravner(width: usize, height: usize) -> SnowflakeSimContext {
+        return SnowflakeSimContext::from_model(Box::new(gravner_sim::GravnerSim::new(width, height)));
+    }
+
+    /// Create a simulation context using a growth model by name,
+    /// with its default parameters
+    pub fn new_with_model(model: &str, width: usize, height: usize) -> Option<SnowflakeSimContext> {
+        return growth_model::create_model(model, width, height).map(SnowflakeSimContext::from_model);
     }
 
     /// Set the water level of a cell
     pub fn set_cell(&mut self, x: usize, y: usize, water: f64) {
         self.sim.set_water(x, y, water);
+        self.record(sim_history::Edit::Water(x, y, water));
+    }
+
+    /// Place a seed crystal of a single cell
+    pub fn seed_single(&mut self, x: usize, y: usize) {
+        self.place_seed(seed_crystal::SeedShape::Single { x, y });
+    }
+
+    /// Place a filled hexagonal seed crystal of the cells
+    /// within `radius` steps of a cell
+    pub fn seed_hexagon(&mut self, x: usize, y: usize, radius: usize) {
+        self.place_seed(seed_crystal::SeedShape::Hexagon { x, y, radius });
+    }
+
+    /// Place a needle shaped seed crystal of `length` cells centered on a
+    /// cell, along axis 0, 1 or 2 of the grid
+    pub fn seed_line(&mut self, x: usize, y: usize, length: usize, axis: usize) {
+        self.place_seed(seed_crystal::SeedShape::Line { x, y, length, axis });
+    }
+
+    /// Place a ring shaped seed crystal of the cells
+    /// exactly `radius` steps from a cell
+    pub fn seed_ring(&mut self, x: usize, y: usize, radius: usize) {
+        self.place_seed(seed_crystal::SeedShape::Ring { x, y, radius });
+    }
+
+    /// Place `count` frozen nuclei at random cells within `radius` steps
+    /// of a cell. The cells only depend on `seed`.
+    pub fn seed_scatter(&mut self, x: usize, y: usize, radius: usize, count: usize, seed: u64) {
+        self.place_seed(seed_crystal::SeedShape::Scatter { x, y, radius, count, seed });
+    }
+
+    /// Place a seed crystal from a bitmap centered on a cell
+    ///
+    /// * `width`, `height` - size of the bitmap
+    /// * `cells` - one byte per cell in row order, non-zero cells are frozen
+    ///
+    /// Returns false if the bitmap does not match its size.
+    pub fn seed_mask(&mut self, x: usize, y: usize, width: usize, height: usize, cells: Vec<u8>) -> bool {
+        if width.checked_mul(height) != Some(cells.len()) {
+            return false;
+        }
+        let mask = seed_crystal::CellMask::from_fn(width, height, |mx, my| cells[my * width + mx] != 0);
+        self.place_seed(seed_crystal::SeedShape::Mask { x, y, mask });
+        return true;
     }
 
     /// Step the Snowflake simulation one iteration
     pub fn step_simulation(&mut self) {
         self.sim.step();
+        self.rewind.capture(self.sim.as_ref());
+    }
 
-        self.sim_history.track_tick(&mut self.sim);
+    /// Rewind the simulation up to `iterations` iterations, limited by
+    /// the past states kept. Changes recorded after the new iteration are
+    /// discarded from the history, so sharing the simulation reproduces
+    /// the new branch. Returns the new iteration count. Has no effect
+    /// during playback, use `seek` instead.
+    pub fn rewind(&mut self, iterations: usize) -> usize {
+        if self.sim_history.state() != sim_history::HistoryState::Tracking {
+            return self.sim.iteration_count();
+        }
+        let oldest = self.rewind.oldest().unwrap_or(0);
+        let target = self.sim.iteration_count().saturating_sub(iterations).max(oldest);
+        if self.rewind.restore(self.sim.as_mut(), target).is_some() {
+            // Past states are taken before the changes of their iteration,
+            // so replay the recorded changes up to and including the target
+            loop {
+                self.sim_history.apply_tick(self.sim.as_mut());
+                if self.sim.iteration_count() >= target {
+                    break;
+                }
+                self.sim.step();
+                self.rewind.capture(self.sim.as_ref());
+            }
+            self.sim_history.truncate(target);
+        }
+        return self.sim.iteration_count();
+    }
+
+    /// Set how many past states are kept for rewinding, and how many
+    /// iterations apart they are. Clears the kept states. Rewinding is off
+    /// until this is called, and a capacity of 0 turns it off again.
+    /// States are kept from the next captured iteration on, or from the
+    /// start when called before `init_tracking`.
+    pub fn set_rewind_buffer(&mut self, capacity: usize, interval: usize) {
+        self.rewind.resize(capacity, interval);
     }
 
     // Playback related
 
+    /// Start the simulation history tracking from the current state, if it
+    /// is not tracked yet, and capture the state for rewinding. Tracking
+    /// starts when the context is created, and every later change made
+    /// through the context is recorded, so the recorded changes are kept.
+    /// Has no effect after the first iteration or during playback.
     pub fn init_tracking(&mut self) {
-        if self.sim.iteration_count == 0 {
-            // Initate simulation history tracking on first iteration
-            self.sim_history.init_tracking(&self.sim);
+        if self.sim.iteration_count() == 0 && self.sim_history.state() != sim_history::HistoryState::Playback {
+            if self.sim_history.state() == sim_history::HistoryState::Idle {
+                self.sim_history.init_tracking(self.sim.as_ref());
+            }
+            // Past states are taken before the changes of their iteration
+            self.rewind.clear();
+            if let Ok(start) = self.sim_history.start_sim() {
+                self.rewind.capture(start.as_ref());
+            }
         }
     }
 
-    /// Initiate the playback of a simulation
-    pub fn init_playback(&mut self, sim_repr_str: String) {
-        self.sim_history = sim_history::SimStateHistory::deserialize_from_str(sim_repr_str);
-        self.sim = self.sim_history.init_playback();
+    /// Initiate the playback of a simulation. Returns an error, which is
+    /// thrown as an `Error` in JS, if the string is not a valid simulation
+    /// history. The context is left unchanged in that case.
+    pub fn init_playback(&mut self, sim_repr_str: String) -> Result<(), HistoryError> {
+        let mut sim_history = sim_history::SimStateHistory::deserialize_from_str(sim_repr_str)?;
+        let sim = sim_history.init_playback()?;
+        // The history can use a different grid size than the context
+        self.vertex_positions.resize(sim.width() * sim.height() * 2 * 4 * 3, 0.0);
+        self.vertex_colors.resize(sim.width() * sim.height() * 4 * 4 * 3, 0.0);
+        self.sim = sim;
+        self.sim_history = sim_history;
+        self.rewind.clear();
+        return Ok(());
     }
 
     /// Step the Snowflake simulation one iteration based on the playback
     pub fn step_simulation_playback(&mut self) {
         self.sim.step();
-        self.sim_history.playback_tick(&mut self.sim);
+        self.sim_history.playback_tick(self.sim.as_mut());
+    }
+
+    /// Jump to an iteration of the playback, forwards or backwards.
+    /// Keyframes of the playback are used to avoid stepping from the start.
+    /// Returns false if no playback is running.
+    pub fn seek(&mut self, iteration: usize) -> bool {
+        if self.sim_history.state() != sim_history::HistoryState::Playback {
+            return false;
+        }
+        self.sim_history.seek(self.sim.as_mut(), iteration);
+        return true;
+    }
+
+    /// Set the amount of iterations between playback keyframes. Shorter
+    /// intervals make seeking faster, but use more memory.
+    pub fn set_keyframe_interval(&mut self, interval: usize) {
+        self.sim_history.set_keyframe_interval(interval);
+    }
+
+    /// Get the amount of iterations which have been simulated
+    pub fn get_iteration_count(&self) -> usize {
+        return self.sim.iteration_count();
     }
 
     /// Get a string representation of the simulation 
@@ -78,14 +229,38 @@ impl SnowflakeSimContext {
         return self.sim_history.serialize_to_str();
     }
 
+    // Snapshots
+
+    /// Save the complete state of the simulation as a compressed snapshot,
+    /// which restores it exactly without replaying it
+    pub fn save_snapshot(&self) -> Vec<u8> {
+        return snapshot::save_snapshot(self.sim.as_ref());
+    }
+
+    /// Replace the simulation with one loaded from a snapshot. Returns an
+    /// error, which is thrown as an `Error` in JS, if the data is not a valid
+    /// snapshot, leaving the context unchanged. A snapshot does not contain
+    /// the history of the simulation, so history tracking, sharing and
+    /// rewinding are unavailable for the loaded simulation.
+    pub fn load_snapshot(&mut self, data: &[u8]) -> Result<(), SnapshotError> {
+        let sim = snapshot::load_snapshot(data)?;
+        self.vertex_positions.resize(sim.width() * sim.height() * 2 * 4 * 3, 0.0);
+        self.vertex_colors.resize(sim.width() * sim.height() * 4 * 4 * 3, 0.0);
+        self.sim = sim;
+        self.sim_history = sim_history::SimStateHistory::new();
+        self.rewind.clear();
+        return Ok(());
+    }
+
     // Graphics related
 
     /// Create the vertex position buffer representing
     /// the hexagonal simulation
+    #[allow(clippy::identity_op)]
     pub fn create_vertex_positions(&mut self) {
         let mut i = 0;
-        for y in 0..self.sim.height {
-            for x in 0..self.sim.width {
+        for y in 0..self.sim.height() {
+            for x in 0..self.sim.width() {
                 let (px, py) = hex_pixel_coord(x, y, HEX_SIZE);
                 let corners = [
                     hex_corner(px, py, HEX_SIZE, 0),
@@ -110,15 +285,16 @@ impl SnowflakeSimContext {
     /// Get the amount of vertices in the vertex position buffer
     /// for the simulation
     pub fn get_vertex_count(&self) -> usize {
-        return self.sim.width * self.sim.height * 2 * 4;
+        return self.sim.width() * self.sim.height() * 2 * 4;
     }
 
     /// Update the vertex color buffer based on the
     /// current state of the simulation.
+    #[allow(clippy::identity_op)]
     pub fn update_vertex_colors(&mut self) {
         let mut i = 0;
-        for y in 0..self.sim.height {
-            for x in 0..self.sim.width {
+        for y in 0..self.sim.height() {
+            for x in 0..self.sim.width() {
                 let water = self.sim.get_water(x, y) as f32;
                 let color = if water < COLOR_CUTTOFF { 0.0 } else { water };
                 let alpha = if color == 0.0 && self.transparent_background { 0.0 } else { 1.0 };
@@ -134,56 +310,316 @@ impl SnowflakeSimContext {
         }
     }
 
+    /// Get a copy of the vertex position buffer
     pub fn get_vertex_positions(&self) -> js_sys::Float32Array {
         return js_sys::Float32Array::from(&self.vertex_positions[..]);
     }
 
+    /// Get a copy of the vertex color buffer
     pub fn get_vertex_colors(&self) -> js_sys::Float32Array {
         return js_sys::Float32Array::from(&self.vertex_colors[..]);
     }
+
+    /// Get a view of the vertex position buffer in wasm memory, without
+    /// copying it. See `get_vertex_colors_view` for when the view is valid.
+    pub fn get_vertex_positions_view(&self) -> js_sys::Float32Array {
+        // Safety: the view is only valid while wasm memory is unchanged,
+        // which the JS side has to follow as documented
+        return unsafe { js_sys::Float32Array::view(&self.vertex_positions[..]) };
+    }
+
+    /// Get a view of the vertex color buffer in wasm memory, without
+    /// copying it, so it can be uploaded to WebGL directly.
+    ///
+    /// The view must be used right away and not kept. It becomes invalid
+    /// with the next call into the simulation, as any call can grow the
+    /// wasm memory, which detaches the view, and changing the grid size
+    /// moves the buffer. The values change with `update_vertex_colors`.
+    pub fn get_vertex_colors_view(&self) -> js_sys::Float32Array {
+        // Safety: see above, the view does not outlive the buffer as long
+        // as the JS side follows these rules
+        return unsafe { js_sys::Float32Array::view(&self.vertex_colors[..]) };
+    }
+
+    /// Render the current state of the simulation as a PNG image.
+    /// Uses the transparent background setting of the context. Returns an
+    /// error, which is thrown as an `Error` in JS, if a size is zero.
+    ///
+    /// * `width`, `height` - size of the image in pixels
+    /// * `red`, `green`, `blue` - tint color, between 0.0 and 1.0
+    pub fn get_png(&self, width: u32, height: u32, red: f32, green: f32, blue: f32) -> Result<Vec<u8>, JsValue> {
+        let options = image_export::ImageOptions {
+            width,
+            height,
+            tint: [red, green, blue, 1.0],
+            transparent_background: self.transparent_background,
+        };
+        return image_export::render_png(self.sim.as_ref(), &options)
+            .map_err(|err| js_sys::Error::new(&err.to_string()).into());
+    }
+
+    /// Export the outline of the frozen crystal as an SVG image
+    ///
+    /// * `contour_levels` - water levels to additionally draw outlines of
+    pub fn get_svg(&self, contour_levels: Vec<f64>) -> String {
+        let options = svg_export::SvgOptions { contour_levels, ..svg_export::SvgOptions::default() };
+        return svg_export::render_svg(self.sim.as_ref(), &options);
+    }
+
+    /// Export the simulation as a 3D relief in the binary STL format,
+    /// where the height of every cell is based on its water level
+    ///
+    /// * `min_level` - only include cells with at least this water level
+    pub fn get_stl(&self, min_level: f64) -> Vec<u8> {
+        let options = mesh_export::MeshOptions { min_level, ..mesh_export::MeshOptions::default() };
+        return mesh_export::build_mesh(self.sim.as_ref(), &options).to_stl_binary();
+    }
+
+    /// Export the simulation as a 3D relief in the OBJ format,
+    /// where the height of every cell is based on its water level
+    ///
+    /// * `min_level` - only include cells with at least this water level
+    pub fn get_obj(&self, min_level: f64) -> String {
+        let options = mesh_export::MeshOptions { min_level, ..mesh_export::MeshOptions::default() };
+        return mesh_export::build_mesh(self.sim.as_ref(), &options).to_obj();
+    }
     
     // Getters and setters for simulation parameters
 
+    /// Set a named parameter of the growth model.
+    /// Returns false if the model has no such parameter.
+    pub fn set_param(&mut self, name: &str, value: f64) -> bool {
+        if !self.sim.set_param(name, value) {
+            return false;
+        }
+        self.record(sim_history::Edit::Param(name.to_string(), value));
+        return true;
+    }
+
+    /// Get a named parameter of the growth model,
+    /// or `None` if the model has no such parameter.
+    pub fn get_param(&self, name: &str) -> Option<f64> {
+        return self.sim.get_param(name);
+    }
+
+    /// Get the names of all parameters of the growth model
+    pub fn get_param_names(&self) -> js_sys::Array {
+        return self.sim.param_names().iter().map(|name| JsValue::from_str(name)).collect();
+    }
+
+    /// Get the name of the growth model
+    pub fn get_model_name(&self) -> String {
+        return self.sim.model_name().to_string();
+    }
+
     /// Set the alpha (vapor diffusion) parameter of the Snowflake Simulation
     pub fn set_alpha(&mut self, value: f64) {
-        self.sim.vapor_diffusion = value;
+        self.set_param("alpha", value);
     }
 
     /// Set the beta (background_vapor) parameter of the Snowflake Simulation
     pub fn set_beta(&mut self, value: f64) {
-        self.sim.background_vapor = value;
-        if self.sim.iteration_count == 0 { 
-            // We need to update the starting background vapor
-            self.sim.fill_starting_background_vapor();
-        }
+        self.set_param("beta", value);
     }
 
     /// Set the gamma (vapor_addition) parameter of the Snowflake Simulation
     pub fn set_gamma(&mut self, value: f64) {
-        self.sim.vapor_addition = value;
+        self.set_param("gamma", value);
     }
 
     /// Set the alpha randomization value of the Snowflake Simulation
     /// * `range` - the percentage range of the random change of the parameter
     pub fn set_alpha_rand(&mut self, range: f64) {
-        self.sim.vapor_diffusion_rand = range;
+        self.set_param("alpha_rand", range);
     }
 
     /// Set the beta randomization value of the Snowflake Simulation
     /// * `range` - the percentage range of the random change of the parameter
     pub fn set_beta_rand(&mut self, range: f64) {
-        self.sim.background_vapor_rand = range;
+        self.set_param("beta_rand", range);
     }
 
     /// Set the gamma randomization value of the Snowflake Simulation
     /// * `range` - the percentage range of the random change of the parameter
     pub fn set_gamma_rand(&mut self, range: f64) {
-        self.sim.vapor_addition_rand = range;
+        self.set_param("gamma_rand", range);
     }
 
     /// Set the random seed of the simulation
     pub fn set_random_seed(&mut self, seed : u64) {
         self.sim.set_random_seed(seed);
+        self.record(sim_history::Edit::Seed(seed));
+    }
+
+    /// Set how random numbers are generated by Reiters model, by name.
+    /// `counter` is the default, `buffer` reproduces simulations from
+    /// before counter mode. Returns false for unknown names, or if the
+    /// growth model is not Reiters model.
+    pub fn set_random_mode(&mut self, name: &str) -> bool {
+        let mode = match sim::RandomMode::from_name(name) {
+            Some(mode) => mode,
+            None => return false,
+        };
+        match self.reiter_sim_mut() {
+            Some(reiter_sim) => reiter_sim.set_random_mode(mode),
+            None => return false,
+        }
+        self.record(sim_history::Edit::RandomMode(mode));
+        return true;
+    }
+
+    /// Modulate alpha, beta or gamma per cell by a noise field, which
+    /// varies smoothly over space and time
+    ///
+    /// * `param` - alpha, beta or gamma
+    /// * `kind` - value or perlin
+    /// * `amplitude` - the parameter is multiplied by 1 + amplitude * noise,
+    ///   where the noise is between -1 and 1
+    /// * `scale` - size of the largest features, in cells
+    /// * `octaves` - amount of noise layers of decreasing size
+    /// * `period` - iterations for the field to change, 0 for a static field
+    ///
+    /// Returns false for unknown parameters or kinds, invalid fields, see
+    /// `NoiseField::is_valid`, or if the growth model is not Reiters model.
+    pub fn set_noise_field(&mut self, param: &str, kind: &str, amplitude: f64, scale: f64, octaves: u32, period: f64) -> bool {
+        let kind = match noise::NoiseKind::from_name(kind) {
+            Some(kind) => kind,
+            None => return false,
+        };
+        let field = noise::NoiseField { kind, amplitude, scale, octaves, period };
+        return self.set_noise(param, Some(field));
+    }
+
+    /// Remove the noise field of alpha, beta or gamma.
+    /// Returns false if there is no such parameter.
+    pub fn clear_noise_field(&mut self, param: &str) -> bool {
+        return self.set_noise(param, None);
+    }
+
+    /// Set how the edge of the grid interacts with the vapor, one of fixed,
+    /// circular, absorbing, periodic or reflecting. Returns false for
+    /// unknown boundaries, periodic boundaries on a grid with an odd
+    /// height or if the growth model is not Reiters model.
+    pub fn set_boundary(&mut self, name: &str) -> bool {
+        let boundary = match sim::Boundary::from_name(name) {
+            Some(boundary) => boundary,
+            None => return false,
+        };
+        return match self.reiter_sim_mut() {
+            Some(sim) => {
+                if !sim.set_boundary(boundary) {
+                    return false;
+                }
+                self.record(sim_history::Edit::Boundary(boundary));
+                true
+            }
+            None => false,
+        };
+    }
+
+    /// Make a parameter follow a schedule of keyframes over the iterations,
+    /// which overrides setting it directly
+    ///
+    /// * `param` - name of any parameter of Reiters model
+    /// * `interpolation` - linear, smoothstep or cubic
+    /// * `iterations`, `values` - the keyframes
+    ///
+    /// Returns false for unknown parameters or interpolations, if there
+    /// are no keyframes, if a value is not finite or if the growth model
+    /// is not Reiters model.
+    pub fn set_schedule(&mut self, param: &str, interpolation: &str, iterations: Vec<u32>, values: Vec<f64>) -> bool {
+        let interpolation = match schedule::Interpolation::from_name(interpolation) {
+            Some(interpolation) => interpolation,
+            None => return false,
+        };
+        if iterations.is_empty() || iterations.len() != values.len() {
+            return false;
+        }
+        let keyframes: Vec<(usize, f64)> = iterations.iter().map(|i| *i as usize).zip(values).collect();
+        return self.set_param_schedule(param, Some(schedule::Schedule::from_keyframes(interpolation, &keyframes)));
+    }
+
+    /// Stop a parameter from following a schedule.
+    /// Returns false if there is no such parameter.
+    pub fn clear_schedule(&mut self, param: &str) -> bool {
+        return self.set_param_schedule(param, None);
+    }
+
+    /// Enable symmetric mode for Reiters model, where only a 1/12 wedge
+    /// around the center cell is simulated. Returns false if the growth
+    /// model does not support symmetric mode.
+    pub fn enable_symmetry(&mut self, cx: usize, cy: usize) -> bool {
+        return match self.reiter_sim_mut() {
+            Some(sim) => {
+                sim.enable_symmetry(cx, cy);
+                self.record(sim_history::Edit::Symmetry(Some((cx, cy))));
+                true
+            }
+            None => false,
+        };
+    }
+
+    /// Disable symmetric mode for Reiters model
+    pub fn disable_symmetry(&mut self) {
+        if let Some(sim) = self.reiter_sim_mut() {
+            sim.disable_symmetry();
+            self.record(sim_history::Edit::Symmetry(None));
+        }
+    }
+
+    /// Is symmetric mode enabled?
+    pub fn is_symmetric(&self) -> bool {
+        return self.sim.as_any().downcast_ref::<sim::SnowflakeSim>()
+            .is_some_and(|sim| sim.is_symmetric());
+    }
+
+    /// Set when Reiters model has finished growing, see `status`. Every
+    /// condition is optional, and stalls are counted from the current
+    /// iteration. Returns false if the growth model is not Reiters model.
+    ///
+    /// * `edge_distance` - steps from the crystal to the edge,
+    ///   1 for a crystal touching it
+    /// * `stall_iterations` - iterations without a cell freezing
+    /// * `mass_tolerance` - relative change of the water of the frozen cells
+    ///   over `termination::MASS_WINDOW` iterations
+    /// * `max_radius` - distance from the seed to the furthest frozen cell
+    pub fn set_stop_conditions(&mut self, edge_distance: Option<usize>, stall_iterations: Option<usize>,
+                               mass_tolerance: Option<f64>, max_radius: Option<f64>) -> bool {
+        let conditions = termination::StopConditions { edge_distance, stall_iterations, mass_tolerance, max_radius };
+        return match self.reiter_sim_mut() {
+            Some(sim) => {
+                sim.set_stop_conditions(conditions);
+                true
+            }
+            None => false,
+        };
+    }
+
+    /// Get whether the simulation is still growing, or the stop condition
+    /// it met. One of growing, edge, stalled, converged or radius.
+    pub fn status(&self) -> String {
+        return self.growth_status().name().to_string();
+    }
+
+    /// Has the simulation met a stop condition? Cheaper than `status`
+    /// for checking after every step, as no string is returned.
+    pub fn is_stopped(&self) -> bool {
+        return self.growth_status().is_stopped();
+    }
+
+    /// Measure the shape of the crystal, see `morphology::measure`
+    ///
+    /// * `seed_x`, `seed_y` - grid position the crystal grew from
+    pub fn get_morphology(&self, seed_x: usize, seed_y: usize) -> morphology::Morphology {
+        return morphology::measure(self.sim.as_ref(), (seed_x, seed_y));
+    }
+
+    /// Compare the crystal with its hexagonal symmetry images,
+    /// see `symmetry_score::measure`
+    ///
+    /// * `seed_x`, `seed_y` - grid position the crystal grew from
+    pub fn get_symmetry_score(&self, seed_x: usize, seed_y: usize) -> symmetry_score::SymmetryScore {
+        return symmetry_score::measure(self.sim.as_ref(), (seed_x, seed_y));
     }
 
     /// Set whether the background should be transparent or not
@@ -192,23 +628,105 @@ impl SnowflakeSimContext {
     }
 
     pub fn get_alpha(&self) -> f64 {
-        return self.sim.vapor_diffusion;
+        return self.sim.get_param("alpha").unwrap_or(0.0);
     }
 
     pub fn get_beta(&self) -> f64 {
-        return self.sim.background_vapor;
+        return self.sim.get_param("beta").unwrap_or(0.0);
     }
 
     pub fn get_gamma(&self) -> f64 {
-        return self.sim.vapor_addition;
+        return self.sim.get_param("gamma").unwrap_or(0.0);
     }
 
     pub fn get_alpha_rand(&self) -> f64 {
-        return self.sim.vapor_diffusion_rand;
+        return self.sim.get_param("alpha_rand").unwrap_or(0.0);
     }
 
     pub fn get_seed(&self) -> u64 {
-        return self.sim.seed;
+        return self.sim.seed();
+    }
+}
+
+impl SnowflakeSimContext {
+    /// Create a simulation context driving any growth model.
+    /// The parameters and frozen cells of the model are recorded
+    /// as the starting state of the simulation history.
+    pub fn from_model(sim: Box<dyn GrowthModel>) -> SnowflakeSimContext {
+        let (width, height) = (sim.width(), sim.height());
+        let mut sim_history = sim_history::SimStateHistory::new();
+        sim_history.init_tracking(sim.as_ref());
+        // Capturing states compresses the whole grid, so rewinding is off
+        // until `set_rewind_buffer` is called
+        let rewind = rewind::RewindBuffer::new(0, rewind::DEFAULT_REWIND_INTERVAL);
+        SnowflakeSimContext {
+            sim,
+            rewind,
+            vertex_positions: vec![0.0; width * height * 2 * 4 * 3],
+            vertex_colors: vec![0.0; width * height * 4 * 4 * 3],
+            sim_history,
+            transparent_background: false
+        }
+    }
+
+    /// Record a change to the simulation in the simulation history
+    fn record(&mut self, edit: sim_history::Edit) {
+        let tick = self.sim.iteration_count();
+        self.sim_history.record(tick, edit);
+    }
+
+    fn place_seed(&mut self, shape: seed_crystal::SeedShape) {
+        for (x, y) in shape.cells(self.sim.width(), self.sim.height()) {
+            self.sim.set_water(x, y, 1.0);
+        }
+        self.record(sim_history::Edit::Crystal(shape));
+    }
+
+    fn set_noise(&mut self, param: &str, field: Option<noise::NoiseField>) -> bool {
+        let accepted = match self.reiter_sim_mut() {
+            Some(reiter_sim) => reiter_sim.set_noise_field(param, field),
+            None => false,
+        };
+        if accepted {
+            self.record(sim_history::Edit::Noise(param.to_string(), field));
+        }
+        return accepted;
+    }
+
+    fn set_param_schedule(&mut self, param: &str, schedule: Option<schedule::Schedule>) -> bool {
+        let accepted = match self.reiter_sim_mut() {
+            Some(reiter_sim) => reiter_sim.set_schedule(param, schedule.clone()),
+            None => false,
+        };
+        if accepted {
+            self.record(sim_history::Edit::Schedule(param.to_string(), schedule));
+        }
+        return accepted;
+    }
+
+    /// Get the growth status, models without stop conditions keep growing
+    fn growth_status(&self) -> termination::GrowthStatus {
+        return match self.sim.as_any().downcast_ref::<sim::SnowflakeSim>() {
+            Some(sim) => sim.status(),
+            None => termination::GrowthStatus::Growing,
+        };
+    }
+
+    /// Get the simulation as Reiters model, if that is the growth model used
+    fn reiter_sim_mut(&mut self) -> Option<&mut sim::SnowflakeSim> {
+        return self.sim.as_any_mut().downcast_mut::<sim::SnowflakeSim>();
+    }
+}
+
+impl From<HistoryError> for JsValue {
+    fn from(err: HistoryError) -> JsValue {
+        return js_sys::Error::new(&err.to_string()).into();
+    }
+}
+
+impl From<SnapshotError> for JsValue {
+    fn from(err: SnapshotError) -> JsValue {
+        return js_sys::Error::new(&err.to_string()).into();
     }
 }
 
@@ -217,7 +735,8 @@ impl SnowflakeSimContext {
 /// * `cy`, `cx` - position of the center of the hexagon
 /// * `size` - size of the hexagon, from center to corner
 /// * `i` - which corner to get the position for, between 0-5
-fn hex_corner(cx: f32, cy: f32, hex_size: f32, i: usize) -> (f32, f32) {
+#[allow(clippy::excessive_precision, clippy::approx_constant)]
+pub(crate) fn hex_corner(cx: f32, cy: f32, hex_size: f32, i: usize) -> (f32, f32) {
     let angle_deg = (60 * (i as isize) - 30) as f32;
     let angle_rad = 3.14159265 / 180.0 * angle_deg;
     return (
@@ -230,10 +749,36 @@ fn hex_corner(cx: f32, cy: f32, hex_size: f32, i: usize) -> (f32, f32) {
 ///
 /// * `ix`, `iy` - integer position of the hexagon
 /// * `hex_size` - size of the hexagon, from center to corner
-fn hex_pixel_coord(ix: usize, iy: usize, hex_size: f32) -> (f32, f32) {
+#[allow(clippy::unnecessary_cast)]
+pub(crate) fn hex_pixel_coord(ix: usize, iy: usize, hex_size: f32) -> (f32, f32) {
     let x: f32 = ix as f32;
     let y: f32 = iy as f32;
     let rx = hex_size * (3.0 as f32).sqrt() * ((x as f32) + 0.5 * (iy % 2 == 1) as usize as f32);
     let ry = hex_size * 3.0 / 2.0 * y;
     return (rx, ry);
 }
+
+/// A corner position on the hexagonal lattice. Corners lie on multiples
+/// of half the hexagon width horizontally and half the size vertically,
+/// which lets corners shared between cells compare equal.
+pub(crate) type CornerKey = (i64, i64);
+
+pub(crate) fn corner_key((x, y): (f32, f32)) -> CornerKey {
+    let half_width = HEX_SIZE * 3.0f32.sqrt() / 2.0;
+    return ((x / half_width).round() as i64, (y / (HEX_SIZE / 2.0)).round() as i64);
+}
+
+/// Get the neighbouring cell sharing the edge between corner `i`
+/// and corner `i + 1` of a cell, in unpadded coordinates where odd
+/// rows are shifted right, see `hex_pixel_coord`
+pub(crate) fn edge_neighbour(x: isize, y: isize, i: usize) -> (isize, isize) {
+    let shift = y & 1;
+    return match i {
+        0 => (x + 1, y),
+        1 => (x + shift, y + 1),
+        2 => (x + shift - 1, y + 1),
+        3 => (x - 1, y),
+        4 => (x + shift - 1, y - 1),
+        _ => (x + shift, y - 1),
+    };
+}
@@ -0,0 +1,261 @@
+use super::growth_model::GrowthModel;
+use super::{corner_key, edge_neighbour, hex_corner, hex_pixel_coord, CornerKey, HEX_SIZE};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Settings for exporting a simulation as a 3D relief
+#[derive(Clone, Debug)]
+pub struct MeshOptions {
+    /// Model units per hexagon size, such as millimeters
+    pub scale: f32,
+    /// Height of a cell without water, in hexagon sizes.
+    /// Must be above 0.0 for every cell to have volume.
+    pub base_height: f32,
+    /// Height added per unit of water, in hexagon sizes
+    pub height_scale: f32,
+    /// Only cells with at least this water level are included.
+    /// 1.0 exports only the frozen crystal.
+    pub min_level: f64,
+}
+
+impl Default for MeshOptions {
+    fn default() -> MeshOptions {
+        return MeshOptions {
+            scale: 1.0,
+            base_height: 1.0,
+            height_scale: 2.0,
+            min_level: 0.0,
+        };
+    }
+}
+
+/// An indexed triangle mesh. Triangles wind counter-clockwise
+/// when seen from outside of the mesh.
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub vertices: Vec<[f32; 3]>,
+    pub triangles: Vec<[u32; 3]>,
+}
+
+/// Build a watertight relief of a simulation, where every included cell is
+/// a hexagonal prism standing on z = 0 with a height based on its water level.
+/// Neighbouring prisms are merged, so only the walls between cells of different
+/// heights are created, and those walls share their vertices with every
+/// face meeting them.
+pub fn build_mesh(sim: &dyn GrowthModel, options: &MeshOptions) -> Mesh {
+    let included = |x: isize, y: isize| {
+        return x >= 0 && y >= 0 && (x as usize) < sim.width() && (y as usize) < sim.height()
+            && sim.get_water(x as usize, y as usize) >= options.min_level;
+    };
+    let cell_height = |x: isize, y: isize| {
+        if !included(x, y) {
+            return 0.0;
+        }
+        return options.base_height + options.height_scale * sim.get_water(x as usize, y as usize) as f32;
+    };
+
+    // Every height meeting in a corner, which vertical edges are split at
+    // so that walls never end in the middle of another face
+    let mut corner_heights: HashMap<CornerKey, Vec<f32>> = HashMap::new();
+    for y in 0..sim.height() as isize {
+        for x in 0..sim.width() as isize {
+            if !included(x, y) {
+                continue;
+            }
+            let (cx, cy) = hex_pixel_coord(x as usize, y as usize, HEX_SIZE);
+            for i in 0..6 {
+                let heights = corner_heights.entry(corner_key(hex_corner(cx, cy, HEX_SIZE, i))).or_default();
+                heights.push(cell_height(x, y));
+            }
+        }
+    }
+    for heights in corner_heights.values_mut() {
+        heights.push(0.0);
+        heights.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        heights.dedup();
+    }
+
+    let mut builder = MeshBuilder { mesh: Mesh::default(), indices: HashMap::new(), scale: options.scale };
+    for y in 0..sim.height() as isize {
+        for x in 0..sim.width() as isize {
+            if !included(x, y) {
+                continue;
+            }
+            let height = cell_height(x, y);
+            let (cx, cy) = hex_pixel_coord(x as usize, y as usize, HEX_SIZE);
+            let corners: Vec<(f32, f32)> = (0..6).map(|i| hex_corner(cx, cy, HEX_SIZE, i)).collect();
+
+            // Top and bottom faces
+            let top: Vec<u32> = corners.iter().map(|c| builder.vertex(*c, height)).collect();
+            let bottom: Vec<u32> = corners.iter().map(|c| builder.vertex(*c, 0.0)).collect();
+            for i in 1..5 {
+                builder.mesh.triangles.push([top[0], top[i], top[i + 1]]);
+                builder.mesh.triangles.push([bottom[0], bottom[i + 1], bottom[i]]);
+            }
+
+            // Walls are created by the higher of the two cells, down to the
+            // lower cell or the ground. Corners are counter-clockwise, so
+            // the outside of the wall is to the right of the edge.
+            for i in 0..6 {
+                let (nx, ny) = edge_neighbour(x, y, i);
+                let low = cell_height(nx, ny);
+                if low >= height {
+                    continue;
+                }
+                let (p, q) = (corners[i], corners[(i + 1) % 6]);
+                let p_side = wall_side(&corner_heights[&corner_key(p)], low, height);
+                let q_side = wall_side(&corner_heights[&corner_key(q)], low, height);
+                builder.wall(p, &p_side, q, &q_side);
+            }
+        }
+    }
+    return builder.mesh;
+}
+
+impl Mesh {
+    /// Write the mesh in the Wavefront OBJ format
+    pub fn to_obj(&self) -> String {
+        let mut obj = String::new();
+        // Writing to a String can not fail
+        for [x, y, z] in &self.vertices {
+            writeln!(obj, "v {} {} {}", x, y, z).unwrap();
+        }
+        for [a, b, c] in &self.triangles {
+            writeln!(obj, "f {} {} {}", a + 1, b + 1, c + 1).unwrap();
+        }
+        return obj;
+    }
+
+    /// Write the mesh in the ASCII STL format
+    pub fn to_stl_ascii(&self, name: &str) -> String {
+        let mut stl = String::new();
+        writeln!(stl, "solid {}", name).unwrap();
+        for triangle in &self.triangles {
+            let [nx, ny, nz] = self.normal(triangle);
+            writeln!(stl, "  facet normal {} {} {}", nx, ny, nz).unwrap();
+            stl.push_str("    outer loop\n");
+            for i in triangle {
+                let [x, y, z] = self.vertices[*i as usize];
+                writeln!(stl, "      vertex {} {} {}", x, y, z).unwrap();
+            }
+            stl.push_str("    endloop\n  endfacet\n");
+        }
+        writeln!(stl, "endsolid {}", name).unwrap();
+        return stl;
+    }
+
+    /// Write the mesh in the binary STL format
+    pub fn to_stl_binary(&self) -> Vec<u8> {
+        let mut stl = vec![0; 80];
+        stl.extend_from_slice(&(self.triangles.len() as u32).to_le_bytes());
+        for triangle in &self.triangles {
+            let mut values = self.normal(triangle).to_vec();
+            for i in triangle {
+                values.extend_from_slice(&self.vertices[*i as usize]);
+            }
+            for value in values {
+                stl.extend_from_slice(&value.to_le_bytes());
+            }
+            // Attribute byte count
+            stl.extend_from_slice(&[0, 0]);
+        }
+        return stl;
+    }
+
+    /// Get the unit normal of a triangle
+    fn normal(&self, triangle: &[u32; 3]) -> [f32; 3] {
+        let [a, b, c] = triangle.map(|i| self.vertices[i as usize]);
+        let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+        let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+        let n = [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+        let length = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+        if length == 0.0 {
+            return [0.0, 0.0, 0.0];
+        }
+        return [n[0] / length, n[1] / length, n[2] / length];
+    }
+}
+
+/// Get the heights a vertical wall edge is split at, from `low` to `high`
+fn wall_side(heights: &[f32], low: f32, high: f32) -> Vec<f32> {
+    return heights.iter().copied().filter(|h| *h >= low && *h <= high).collect();
+}
+
+/// Helper for creating a mesh with shared vertices
+struct MeshBuilder {
+    mesh: Mesh,
+    indices: HashMap<(CornerKey, u32), u32>,
+    scale: f32,
+}
+
+impl MeshBuilder {
+    /// Get the index of the vertex at a corner and height, creating it if needed
+    fn vertex(&mut self, corner: (f32, f32), height: f32) -> u32 {
+        let key = (corner_key(corner), height.to_bits());
+        if let Some(index) = self.indices.get(&key) {
+            return *index;
+        }
+        let index = self.mesh.vertices.len() as u32;
+        self.mesh.vertices.push([corner.0 * self.scale, corner.1 * self.scale, height * self.scale]);
+        self.indices.insert(key, index);
+        return index;
+    }
+
+    /// Create a vertical wall facing right of the edge from `p` to `q`.
+    /// The wall is triangulated between its two sides, which can be split
+    /// at different heights, without creating degenerate triangles.
+    fn wall(&mut self, p: (f32, f32), p_side: &[f32], q: (f32, f32), q_side: &[f32]) {
+        let (mut i, mut j) = (0, 0);
+        while i + 1 < p_side.len() || j + 1 < q_side.len() {
+            let advance_q = i + 1 == p_side.len() || (j + 1 < q_side.len() && q_side[j + 1] <= p_side[i + 1]);
+            let (p_vertex, q_vertex) = (self.vertex(p, p_side[i]), self.vertex(q, q_side[j]));
+            if advance_q {
+                let next = self.vertex(q, q_side[j + 1]);
+                self.mesh.triangles.push([p_vertex, q_vertex, next]);
+                j += 1;
+            } else {
+                let next = self.vertex(p, p_side[i + 1]);
+                self.mesh.triangles.push([p_vertex, q_vertex, next]);
+                i += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::sim;
+
+    #[test]
+    fn test_mesh_watertight() {
+        let mut simulation = sim::SnowflakeSim::new(30, 30, 1.0, 0.4, 0.0001);
+        simulation.set_water(15, 15, 1.0);
+        for _ in 0..200 {
+            simulation.step();
+        }
+
+        for min_level in [0.0, 1.0] {
+            let options = MeshOptions { min_level, ..MeshOptions::default() };
+            let mesh = build_mesh(&simulation, &options);
+            assert!(!mesh.triangles.is_empty());
+
+            // A closed and consistently oriented mesh uses every directed
+            // edge exactly once, together with its reverse
+            let mut edges: HashMap<(u32, u32), usize> = HashMap::new();
+            for [a, b, c] in &mesh.triangles {
+                assert!(a != b && b != c && a != c);
+                for edge in [(*a, *b), (*b, *c), (*c, *a)] {
+                    *edges.entry(edge).or_default() += 1;
+                }
+            }
+            for ((a, b), count) in &edges {
+                assert_eq!(*count, 1);
+                assert_eq!(edges.get(&(*b, *a)), Some(&1));
+            }
+
+            let stl = mesh.to_stl_binary();
+            assert_eq!(stl.len(), 84 + mesh.triangles.len() * 50);
+        }
+    }
+}
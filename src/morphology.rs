@@ -0,0 +1,372 @@
+use super::growth_model::GrowthModel;
+use super::sim::get_neighbours;
+
+use std::collections::VecDeque;
+use wasm_bindgen::prelude::*;
+
+/// Branches shorter than this amount of cells are treated as roughness
+/// of the crystal surface rather than branches
+const MIN_BRANCH_LENGTH: usize = 3;
+/// Angle in radians around the tip of a primary branch, within which
+/// no other part of the crystal reaches further from the seed
+const PRIMARY_SECTOR: f64 = std::f64::consts::PI / 6.0;
+/// Distance in rows and columns within which a branch tip is the
+/// furthest cell from the seed
+const TIP_NEIGHBOURHOOD: isize = 2;
+
+/// Shape measurements of the frozen cells of a simulation.
+/// Lengths are in cells, the distance between the centers of two
+/// neighbouring cells.
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Morphology {
+    /// Amount of frozen cells
+    pub area: usize,
+    /// Amount of cell edges between frozen and unfrozen cells
+    pub perimeter: usize,
+    /// Distance from the seed to the center of the furthest frozen cell
+    pub max_radius: f64,
+    /// Mean distance from the seed to the centers of the frozen cells
+    pub mean_radius: f64,
+    /// Root mean square distance of the frozen cells from their centroid
+    pub radius_of_gyration: f64,
+    /// Box-counting dimension of the frozen cells, 2 for a filled plate
+    pub fractal_dimension: f64,
+    /// Branches growing from the center of the crystal
+    pub primary_branches: usize,
+    /// Branches growing from the side of another branch
+    pub secondary_branches: usize,
+    /// Areas of unfrozen cells enclosed by the crystal
+    pub holes: usize,
+    /// Grid positions of the tips of the primary and secondary branches
+    #[wasm_bindgen(skip)]
+    pub branch_tips: Vec<(usize, usize)>,
+}
+
+#[wasm_bindgen]
+impl Morphology {
+    /// Get the branch tip positions as x, y pairs
+    pub fn get_branch_tips(&self) -> Vec<usize> {
+        return self.branch_tips.iter().flat_map(|(x, y)| [*x, *y]).collect();
+    }
+}
+
+/// Measure the shape of the crystal of a simulation
+///
+/// * `seed` - grid position the crystal grew from, usually the seed
+///   crystal. Branches are only found in the part of the crystal
+///   connected to the frozen cell closest to it.
+pub fn measure(sim: &dyn GrowthModel, seed: (usize, usize)) -> Morphology {
+    let (width, height) = (sim.width(), sim.height());
+    let frozen: Vec<bool> = (0..width * height).map(|i| sim.is_frozen(i % width, i / width)).collect();
+    let cells: Vec<usize> = (0..width * height).filter(|i| frozen[*i]).collect();
+    if cells.is_empty() {
+        return Morphology::default();
+    }
+    let grid = Grid { width, height, frozen: &frozen };
+    let center = cell_center(seed.0, seed.1);
+    let radius = |i: usize| grid.radius(i, center);
+
+    let mut morphology = Morphology { area: cells.len(), ..Morphology::default() };
+    let area = cells.len() as f64;
+    morphology.perimeter = cells.iter()
+        .map(|i| grid.neighbours(*i).filter(|n| !n.is_some_and(|n| frozen[n])).count())
+        .sum();
+    morphology.max_radius = cells.iter().map(|i| radius(*i)).fold(0.0, f64::max);
+    morphology.mean_radius = cells.iter().map(|i| radius(*i)).sum::<f64>() / area;
+    let centers: Vec<(f64, f64)> = cells.iter().map(|i| cell_center(i % width, i / width)).collect();
+    let centroid = (
+        centers.iter().map(|c| c.0).sum::<f64>() / area,
+        centers.iter().map(|c| c.1).sum::<f64>() / area,
+    );
+    morphology.radius_of_gyration = (centers.iter().map(|c| distance(*c, centroid).powi(2)).sum::<f64>() / area).sqrt();
+    morphology.fractal_dimension = box_counting_dimension(&centers);
+    morphology.holes = grid.holes(&cells);
+
+    let start = *cells.iter().min_by(|a, b| radius(**a).total_cmp(&radius(**b))).unwrap();
+    let branches = grid.branches(start, center);
+    for (tip, primary) in branches {
+        if primary {
+            morphology.primary_branches += 1;
+        } else {
+            morphology.secondary_branches += 1;
+        }
+        morphology.branch_tips.push((tip % width, tip / width));
+    }
+    return morphology;
+}
+
+/// Get the center of the hexagon of a grid position, where odd rows
+/// are shifted right by half a cell
+pub fn cell_center(x: usize, y: usize) -> (f64, f64) {
+    return (x as f64 + 0.5 * (y % 2) as f64, y as f64 * 3f64.sqrt() / 2.0);
+}
+
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    return (a.0 - b.0).hypot(a.1 - b.1);
+}
+
+/// The frozen cells of a grid, indexed without padding
+struct Grid<'a> {
+    width: usize,
+    height: usize,
+    frozen: &'a [bool],
+}
+
+impl Grid<'_> {
+    /// Get the 6 neighbours of a cell in the order of `get_neighbours`,
+    /// `None` for neighbours outside of the grid
+    fn neighbours(&self, i: usize) -> impl Iterator<Item = Option<usize>> {
+        let (width, height) = (self.width as isize, self.height as isize);
+        // Neighbours are found in padded coordinates
+        let neighbours = get_neighbours((i % self.width) as isize + 1, (i / self.width) as isize + 1);
+        return IntoIterator::into_iter(neighbours).map(move |(x, y)| {
+            let (x, y) = (x - 1, y - 1);
+            if x < 0 || y < 0 || x >= width || y >= height {
+                return None;
+            }
+            return Some((y * width + x) as usize);
+        });
+    }
+
+    fn frozen_neighbours(&self, i: usize) -> impl Iterator<Item = usize> + '_ {
+        return self.neighbours(i).flatten().filter(move |n| self.frozen[*n]);
+    }
+
+    /// Count the holes of the frozen cells from their Euler characteristic.
+    /// Cells are vertices of a triangular lattice, connected by an edge when
+    /// they are neighbours, and three neighbouring cells form a face. The
+    /// Euler characteristic V - E + F equals the amount of connected parts
+    /// minus the amount of holes.
+    fn holes(&self, cells: &[usize]) -> usize {
+        let (mut edges, mut faces) = (0, 0);
+        for &i in cells {
+            // Neighbours in the order east, north east, north west, west,
+            // south west and south east
+            let neighbours: Vec<bool> = self.neighbours(i).map(|n| n.is_some_and(|n| self.frozen[n])).collect();
+            // Every edge and face is counted once, from its lower left cell
+            edges += neighbours[..3].iter().filter(|frozen| **frozen).count() as isize;
+            faces += (neighbours[0] && neighbours[1]) as isize + (neighbours[1] && neighbours[2]) as isize;
+        }
+        let euler = cells.len() as isize - edges + faces;
+        return (self.components(cells) as isize - euler).max(0) as usize;
+    }
+
+    /// Count the connected parts of the frozen cells
+    fn components(&self, cells: &[usize]) -> usize {
+        let mut visited = vec![false; self.frozen.len()];
+        let mut count = 0;
+        for &i in cells {
+            if !visited[i] {
+                count += 1;
+                self.flood(i, |n| !std::mem::replace(&mut visited[n], true));
+            }
+        }
+        return count;
+    }
+
+    /// Visit the frozen cells connected to a cell in breadth first order.
+    /// `visit` is called with every cell and its neighbours, and returns
+    /// whether the search should continue from the cell.
+    fn flood(&self, start: usize, mut visit: impl FnMut(usize) -> bool) {
+        let mut queue = VecDeque::new();
+        if visit(start) {
+            queue.push_back(start);
+        }
+        while let Some(i) = queue.pop_front() {
+            for n in self.frozen_neighbours(i) {
+                if visit(n) {
+                    queue.push_back(n);
+                }
+            }
+        }
+    }
+
+    /// Find the branches of the part of the crystal connected to `start`.
+    /// Returns the tip of every branch and whether it is a primary branch.
+    ///
+    /// Tips are the frozen cells furthest from the seed within their
+    /// surroundings. Starting with the tip furthest from the seed, every
+    /// branch is followed back towards the seed, until it joins a branch
+    /// which was already followed. A branch is primary when its tip reaches
+    /// further from the seed than the rest of the crystal in its direction.
+    fn branches(&self, start: usize, center: (f64, f64)) -> Vec<(usize, bool)> {
+        let radius = |i: usize| self.radius(i, center);
+        // Steps from the start through the crystal
+        let mut steps = vec![usize::MAX; self.frozen.len()];
+        steps[start] = 0;
+        let mut connected = vec![start];
+        let mut queue = VecDeque::from([start]);
+        while let Some(i) = queue.pop_front() {
+            for n in self.frozen_neighbours(i).collect::<Vec<usize>>() {
+                if steps[n] == usize::MAX {
+                    steps[n] = steps[i] + 1;
+                    connected.push(n);
+                    queue.push_back(n);
+                }
+            }
+        }
+
+        let mut tips: Vec<usize> = connected.iter().copied()
+            .filter(|i| self.is_tip(*i, &radius))
+            .collect();
+        tips.sort_by(|a, b| radius(*b).total_cmp(&radius(*a)));
+
+        let mut claimed = vec![false; self.frozen.len()];
+        let mut branches = Vec::new();
+        for tip in tips {
+            // Follow the branch back towards the seed
+            let mut i = tip;
+            while !claimed[i] && steps[i] > 0 {
+                claimed[i] = true;
+                let next = self.frozen_neighbours(i)
+                    .filter(|n| steps[*n] + 1 == steps[i])
+                    .min_by(|a, b| radius(*a).total_cmp(&radius(*b)))
+                    .unwrap();
+                i = next;
+            }
+            let length = steps[tip] - steps[i];
+            if length >= MIN_BRANCH_LENGTH {
+                branches.push((tip, self.is_primary(tip, &connected, center)));
+            }
+            claimed[i] = true;
+        }
+        return branches;
+    }
+
+    /// Is a branch tip further from the seed than every connected cell
+    /// within `PRIMARY_SECTOR` of its direction?
+    fn is_primary(&self, tip: usize, connected: &[usize], center: (f64, f64)) -> bool {
+        let angle = |i: usize| {
+            let (x, y) = self.center(i);
+            return (y - center.1).atan2(x - center.0);
+        };
+        let (own_radius, own_angle) = (self.radius(tip, center), angle(tip));
+        return connected.iter().all(|&i| {
+            let difference = (angle(i) - own_angle).abs();
+            let difference = difference.min(2.0 * std::f64::consts::PI - difference);
+            return difference > PRIMARY_SECTOR || self.radius(i, center) <= own_radius;
+        });
+    }
+
+    fn center(&self, i: usize) -> (f64, f64) {
+        return cell_center(i % self.width, i / self.width);
+    }
+
+    /// Get the distance from a point to the center of a cell
+    fn radius(&self, i: usize, center: (f64, f64)) -> f64 {
+        return distance(self.center(i), center);
+    }
+
+    /// Is a frozen cell on the surface, and further from the seed than
+    /// every frozen cell around it?
+    fn is_tip(&self, i: usize, radius: &impl Fn(usize) -> f64) -> bool {
+        if self.frozen_neighbours(i).count() == 6 {
+            return false;
+        }
+        let (x, y) = ((i % self.width) as isize, (i / self.width) as isize);
+        let r = TIP_NEIGHBOURHOOD;
+        let (x0, x1) = ((x - r).max(0) as usize, (x + r).min(self.width as isize - 1) as usize);
+        let (y0, y1) = ((y - r).max(0) as usize, (y + r).min(self.height as isize - 1) as usize);
+        let own = radius(i);
+        for ny in y0..y1 + 1 {
+            for nx in x0..x1 + 1 {
+                let n = ny * self.width + nx;
+                // Equally far cells are broken by index, so only one is a tip
+                if self.frozen[n] && (radius(n) > own || (radius(n) == own && n < i)) {
+                    return false;
+                }
+            }
+        }
+        return true;
+    }
+}
+
+/// Estimate the box-counting dimension of a set of points, from the
+/// slope of the amount of boxes covering them against the box size
+fn box_counting_dimension(points: &[(f64, f64)]) -> f64 {
+    let (min_x, max_x) = points.iter().fold((f64::MAX, f64::MIN), |(lo, hi), p| (lo.min(p.0), hi.max(p.0)));
+    let (min_y, max_y) = points.iter().fold((f64::MAX, f64::MIN), |(lo, hi), p| (lo.min(p.1), hi.max(p.1)));
+    let extent = (max_x - min_x).max(max_y - min_y);
+    // Box sizes from one cell up to a quarter of the crystal, at least two sizes
+    let mut samples = Vec::new();
+    let mut size: f64 = 1.0;
+    while samples.len() < 2 || size <= extent / 4.0 {
+        let mut boxes: Vec<(i64, i64)> = points.iter()
+            .map(|p| (((p.0 - min_x) / size) as i64, ((p.1 - min_y) / size) as i64))
+            .collect();
+        boxes.sort_unstable();
+        boxes.dedup();
+        samples.push((-size.ln(), (boxes.len() as f64).ln()));
+        size *= 2.0;
+    }
+    // Least squares slope
+    let n = samples.len() as f64;
+    let mean_x = samples.iter().map(|s| s.0).sum::<f64>() / n;
+    let mean_y = samples.iter().map(|s| s.1).sum::<f64>() / n;
+    let covariance: f64 = samples.iter().map(|s| (s.0 - mean_x) * (s.1 - mean_y)).sum();
+    let variance: f64 = samples.iter().map(|s| (s.0 - mean_x).powi(2)).sum();
+    return covariance / variance;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::seed_crystal::SeedShape;
+    use super::super::sim::SnowflakeSim;
+
+    #[test]
+    fn test_morphology_shapes() {
+        let measure_shape = |shape: SeedShape| {
+            let mut sim = SnowflakeSim::new(60, 60, 1.0, 0.4, 0.0001);
+            for (x, y) in shape.cells(60, 60) {
+                sim.set_water(x, y, 1.0);
+            }
+            return measure(&sim, (30, 30));
+        };
+
+        let hexagon = measure_shape(SeedShape::Hexagon { x: 30, y: 30, radius: 6 });
+        let plate = measure_shape(SeedShape::Hexagon { x: 30, y: 30, radius: 20 });
+        assert_eq!(hexagon.area, 3 * 6 * 7 + 1);
+        assert_eq!(hexagon.perimeter, 6 * (2 * 6 + 1));
+        assert!((hexagon.max_radius - 6.0).abs() < 1e-9);
+        assert_eq!(hexagon.holes, 0);
+        assert!(plate.fractal_dimension > 1.75 && plate.fractal_dimension <= 2.0);
+        // The corners of a plate are the tips of short primary branches
+        assert_eq!((hexagon.primary_branches, hexagon.secondary_branches), (6, 0));
+
+        let ring = measure_shape(SeedShape::Ring { x: 30, y: 30, radius: 6 });
+        assert_eq!(ring.holes, 1);
+        assert_eq!(ring.area, 6 * 6);
+        assert!((ring.mean_radius - ring.radius_of_gyration).abs() < 0.5);
+
+        // Two rings, one with a gap
+        let mut sim = SnowflakeSim::new(60, 60, 1.0, 0.4, 0.0001);
+        let open_ring = SeedShape::Ring { x: 15, y: 15, radius: 4 }.cells(60, 60);
+        let ring = SeedShape::Ring { x: 40, y: 40, radius: 3 }.cells(60, 60);
+        for (x, y) in open_ring.into_iter().skip(1).chain(ring) {
+            sim.set_water(x, y, 1.0);
+        }
+        assert_eq!(measure(&sim, (15, 15)).holes, 1);
+        assert_eq!(measure(&SnowflakeSim::new(10, 10, 1.0, 0.4, 0.0001), (5, 5)), Morphology::default());
+    }
+
+    #[test]
+    fn test_morphology_dendrite() {
+        let (cx, cy) = (75, 75);
+        let mut sim = SnowflakeSim::new(150, 150, 1.0, 0.4, 0.0001);
+        sim.set_water(cx, cy, 1.0);
+        for _ in 0..900 {
+            sim.step();
+        }
+        let morphology = measure(&sim, (cx, cy));
+        assert_eq!(morphology.primary_branches, 6);
+        assert!(morphology.secondary_branches > 0);
+        assert!(morphology.fractal_dimension > 1.0 && morphology.fractal_dimension < 1.75);
+        assert_eq!(morphology.branch_tips.len(), morphology.primary_branches + morphology.secondary_branches);
+        // The tips of the primary branches are the furthest cells
+        for (x, y) in &morphology.branch_tips[..6] {
+            assert!(distance(cell_center(*x, *y), cell_center(cx, cy)) > morphology.max_radius - 2.0);
+        }
+    }
+}
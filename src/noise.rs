@@ -0,0 +1,188 @@
+use serde::{Deserialize, Serialize};
+
+/// Kind of coherent noise
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NoiseKind {
+    /// Interpolated random values at lattice points
+    Value,
+    /// Interpolated random gradients at lattice points, which has
+    /// fewer grid aligned artifacts than value noise
+    Perlin,
+}
+
+impl NoiseKind {
+    /// Get a noise kind by its name, value or perlin
+    pub fn from_name(name: &str) -> Option<NoiseKind> {
+        return match name {
+            "value" => Some(NoiseKind::Value),
+            "perlin" => Some(NoiseKind::Perlin),
+            _ => None,
+        };
+    }
+}
+
+/// Largest amount of noise layers, as every layer is sampled
+/// for every cell in every step
+pub const MAX_OCTAVES: u32 = 16;
+
+/// A smoothly varying noise field over space and time,
+/// used to modulate a simulation parameter per cell
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NoiseField {
+    pub kind: NoiseKind,
+    /// Strength of the modulation, the parameter is multiplied
+    /// by 1 + amplitude * noise, where the noise is between -1 and 1
+    pub amplitude: f64,
+    /// Size of the largest features, in cells
+    pub scale: f64,
+    /// Amount of noise layers, each with half the size and strength of the
+    /// previous one, at most `MAX_OCTAVES`
+    pub octaves: u32,
+    /// Iterations for the field to change by about one feature,
+    /// 0 for a field which does not change over time
+    pub period: f64,
+}
+
+impl NoiseField {
+    /// Does the field have a finite amplitude, a positive scale, a period
+    /// of at least 0 and at most `MAX_OCTAVES` octaves?
+    pub fn is_valid(&self) -> bool {
+        return self.amplitude.is_finite()
+            && self.scale.is_finite() && self.scale > 0.0
+            && self.period.is_finite() && self.period >= 0.0
+            && self.octaves <= MAX_OCTAVES;
+    }
+
+    /// Sample the noise between -1 and 1 at a position and time
+    ///
+    /// * `seed` - seed of the lattice, different seeds give unrelated fields
+    pub fn sample(&self, seed: u64, x: f64, y: f64, t: f64) -> f64 {
+        let t = if self.period > 0.0 { t / self.period } else { 0.0 };
+        let (mut frequency, mut strength) = (1.0 / self.scale.max(f64::MIN_POSITIVE), 1.0);
+        let (mut sum, mut total) = (0.0, 0.0);
+        for octave in 0..self.octaves.max(1) {
+            let octave_seed = seed.wrapping_add(octave as u64);
+            let value = match self.kind {
+                NoiseKind::Value => value_noise(octave_seed, x * frequency, y * frequency, t),
+                NoiseKind::Perlin => perlin_noise(octave_seed, x * frequency, y * frequency, t),
+            };
+            sum += strength * value;
+            total += strength;
+            frequency *= 2.0;
+            strength *= 0.5;
+        }
+        return sum / total;
+    }
+
+    /// Get the factor a parameter is multiplied by at a position and time,
+    /// with negative values clamped to 0
+    pub fn factor(&self, seed: u64, x: f64, y: f64, t: f64) -> f64 {
+        return (1.0 + self.amplitude * self.sample(seed, x, y, t)).max(0.0);
+    }
+}
+
+/// SplitMix64 finalizer, which mixes the bits of a 64 bit value
+pub(crate) fn splitmix64(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    return z ^ (z >> 31);
+}
+
+/// Hash a lattice point
+fn hash(seed: u64, x: i64, y: i64, t: i64) -> u64 {
+    return splitmix64(splitmix64(splitmix64(seed ^ x as u64) ^ y as u64) ^ t as u64);
+}
+
+/// Quintic fade curve, which makes the noise smooth across lattice cells
+fn fade(t: f64) -> f64 {
+    return t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    return a + (b - a) * t;
+}
+
+/// Interpolate a function of the eight corners of the lattice cell around a point
+fn interpolate(x: f64, y: f64, t: f64, corner: impl Fn(i64, i64, i64, f64, f64, f64) -> f64) -> f64 {
+    let (x0, y0, t0) = (x.floor(), y.floor(), t.floor());
+    let (dx, dy, dt) = (x - x0, y - y0, t - t0);
+    let (x0, y0, t0) = (x0 as i64, y0 as i64, t0 as i64);
+    let mut layers = [0.0; 2];
+    for (k, layer) in layers.iter_mut().enumerate() {
+        let k = k as i64;
+        let c = |i: i64, j: i64| corner(x0 + i, y0 + j, t0 + k, dx - i as f64, dy - j as f64, dt - k as f64);
+        let bottom = lerp(c(0, 0), c(1, 0), fade(dx));
+        let top = lerp(c(0, 1), c(1, 1), fade(dx));
+        *layer = lerp(bottom, top, fade(dy));
+    }
+    return lerp(layers[0], layers[1], fade(dt));
+}
+
+/// Value noise between -1 and 1
+fn value_noise(seed: u64, x: f64, y: f64, t: f64) -> f64 {
+    return interpolate(x, y, t, |ix, iy, it, _, _, _| {
+        // The top 53 bits mapped between -1 and 1
+        return (hash(seed, ix, iy, it) >> 11) as f64 / (1u64 << 52) as f64 - 1.0;
+    });
+}
+
+/// Perlin noise, scaled to lie between -1 and 1
+fn perlin_noise(seed: u64, x: f64, y: f64, t: f64) -> f64 {
+    let value = interpolate(x, y, t, |ix, iy, it, dx, dy, dt| {
+        // One of the 12 gradients towards the edges of a cube
+        return match hash(seed, ix, iy, it) % 12 {
+            0 => dx + dy,
+            1 => -dx + dy,
+            2 => dx - dy,
+            3 => -dx - dy,
+            4 => dx + dt,
+            5 => -dx + dt,
+            6 => dx - dt,
+            7 => -dx - dt,
+            8 => dy + dt,
+            9 => -dy + dt,
+            10 => dy - dt,
+            _ => -dy - dt,
+        };
+    });
+    return value.clamp(-1.0, 1.0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_noise_field() {
+        for kind in [NoiseKind::Value, NoiseKind::Perlin] {
+            let field = NoiseField { kind, amplitude: 0.5, scale: 10.0, octaves: 3, period: 100.0 };
+            let mut min: f64 = 1.0;
+            let mut max: f64 = -1.0;
+            for y in 0..100 {
+                for x in 0..100 {
+                    let (x, y) = (x as f64, y as f64);
+                    let value = field.sample(1, x, y, 50.0);
+                    min = min.min(value);
+                    max = max.max(value);
+                    // Neighbouring cells and iterations have similar values
+                    assert!((value - field.sample(1, x + 1.0, y, 50.0)).abs() < 0.5);
+                    assert!((value - field.sample(1, x, y, 51.0)).abs() < 0.1);
+                }
+            }
+            assert!(min >= -1.0 && max <= 1.0);
+            assert!(max - min > 0.5);
+
+            // Deterministic, and different for another seed
+            assert_eq!(field.sample(1, 12.3, 45.6, 7.0), field.sample(1, 12.3, 45.6, 7.0));
+            assert_ne!(field.sample(1, 12.3, 45.6, 7.0), field.sample(2, 12.3, 45.6, 7.0));
+            assert!(field.factor(1, 12.3, 45.6, 7.0) >= 0.5);
+
+            assert!(field.is_valid());
+            assert!(!NoiseField { octaves: MAX_OCTAVES + 1, ..field }.is_valid());
+            assert!(!NoiseField { scale: 0.0, ..field }.is_valid());
+            assert!(!NoiseField { period: -1.0, ..field }.is_valid());
+            assert!(!NoiseField { amplitude: f64::NAN, ..field }.is_valid());
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use super::growth_model::GrowthModel;
+
+use flate2::write::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use std::collections::VecDeque;
+use std::io::Write;
+
+/// Default amount of iterations between rewind states
+pub const DEFAULT_REWIND_INTERVAL: usize = 10;
+/// Largest amount of frames from a full frame to the next one
+const FULL_FRAME_INTERVAL: usize = 10;
+
+/// A compressed simulation state
+enum Frame {
+    /// The full state
+    Full(Vec<u8>),
+    /// The state XORed with the state of the previous frame. Most cells
+    /// barely change between frames, so this compresses far better.
+    Delta(Vec<u8>),
+}
+
+/// A bounded ring buffer of past simulation states, captured at a
+/// regular interval of iterations. Every few frames is stored in full, so
+/// the oldest states can be dropped up to the next full frame without
+/// recompressing any frame, once the remaining frames fill the buffer.
+pub struct RewindBuffer {
+    capacity: usize,
+    interval: usize,
+    // Iteration and state, oldest first. The oldest frame is always full.
+    frames: VecDeque<(usize, Frame)>,
+    // Uncompressed state of the newest frame, which the next delta is based on
+    last_state: Vec<u8>,
+}
+
+impl RewindBuffer {
+    /// Create an empty rewind buffer
+    ///
+    /// * `capacity` - least amount of states to keep, 0 disables rewinding.
+    ///   Up to `FULL_FRAME_INTERVAL` - 1 more states are kept.
+    /// * `interval` - iterations between states
+    pub fn new(capacity: usize, interval: usize) -> RewindBuffer {
+        return RewindBuffer { capacity, interval: interval.max(1), frames: VecDeque::new(), last_state: Vec::new() };
+    }
+
+    /// Change the size of the buffer, which clears it
+    pub fn resize(&mut self, capacity: usize, interval: usize) {
+        *self = RewindBuffer::new(capacity, interval);
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.last_state.clear();
+    }
+
+    /// Get the iteration of the oldest state
+    pub fn oldest(&self) -> Option<usize> {
+        return self.frames.front().map(|(iteration, _)| *iteration);
+    }
+
+    /// Capture the state of the simulation if it is at a rewind interval
+    pub fn capture(&mut self, sim: &dyn GrowthModel) {
+        let iteration = sim.iteration_count();
+        if self.capacity == 0 || !iteration.is_multiple_of(self.interval)
+            || self.frames.back().is_some_and(|(last, _)| *last >= iteration) {
+            return;
+        }
+        let state = sim.save_state();
+        let deltas = self.frames.iter().rev().take_while(|(_, frame)| matches!(frame, Frame::Delta(_))).count();
+        let full_interval = self.capacity.min(FULL_FRAME_INTERVAL);
+        let frame = if self.frames.is_empty() || state.len() != self.last_state.len() || deltas + 1 >= full_interval {
+            Frame::Full(compress(&state))
+        } else {
+            Frame::Delta(compress(&xor(&state, &self.last_state)))
+        };
+        self.frames.push_back((iteration, frame));
+        self.last_state = state;
+
+        // Drop the oldest frames up to the next full frame, as the deltas
+        // after it can not be restored without the dropped frames
+        while let Some(next_full) = self.frames.iter().skip(1).position(|(_, frame)| matches!(frame, Frame::Full(_))) {
+            let next_full = next_full + 1;
+            if self.frames.len() - next_full < self.capacity {
+                break;
+            }
+            self.frames.drain(..next_full);
+        }
+    }
+
+    /// Restore the latest state at or before `iteration`, and drop every
+    /// later state. Returns the restored iteration, or `None` if there is
+    /// no such state.
+    pub fn restore(&mut self, sim: &mut dyn GrowthModel, iteration: usize) -> Option<usize> {
+        let index = self.frames.partition_point(|(i, _)| *i <= iteration).checked_sub(1)?;
+        self.frames.truncate(index + 1);
+
+        // Rebuild the state from the closest full frame before it
+        let start = (0..=index).rev().find(|i| matches!(self.frames[*i].1, Frame::Full(_))).unwrap();
+        let mut state = Vec::new();
+        for (_, frame) in self.frames.range(start..=index) {
+            state = match frame {
+                Frame::Full(data) => decompress(data),
+                Frame::Delta(data) => xor(&decompress(data), &state),
+            };
+        }
+        if !sim.load_state(&state) {
+            self.clear();
+            return None;
+        }
+        self.last_state = state;
+        return Some(self.frames[index].0);
+    }
+}
+
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    return a.iter().zip(b).map(|(a, b)| a ^ b).collect();
+}
+
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::fast());
+    encoder.write_all(data).unwrap();
+    return encoder.finish().unwrap();
+}
+
+/// Decompress a frame, which was compressed by this module
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut decoder = ZlibDecoder::new(Vec::new());
+    decoder.write_all(data).unwrap();
+    return decoder.finish().unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::sim;
+
+    #[test]
+    fn test_rewind_buffer() {
+        let mut simulation = sim::SnowflakeSim::new(30, 30, 1.0, 0.4, 0.0001);
+        simulation.set_water(15, 15, 1.0);
+        simulation.background_vapor_rand = 0.2;
+
+        let mut rewind = RewindBuffer::new(5, 4);
+        let mut states = Vec::new();
+        for _ in 0..40 {
+            rewind.capture(&simulation);
+            states.push(simulation.save_state());
+            simulation.step();
+        }
+        // Only the last five states, from iteration 20, are kept
+        assert_eq!(rewind.oldest(), Some(20));
+        assert!(rewind.frames.len() < 5 + FULL_FRAME_INTERVAL);
+
+        assert_eq!(rewind.restore(&mut simulation, 30), Some(28));
+        assert!(simulation.save_state() == states[28]);
+        assert_eq!(rewind.restore(&mut simulation, 10), None);
+        // Later states were dropped, and new ones are based on the restored one
+        simulation.step();
+        simulation.step();
+        simulation.step();
+        simulation.step();
+        rewind.capture(&simulation);
+        assert_eq!(rewind.restore(&mut simulation, 35), Some(32));
+        assert!(simulation.save_state() == states[32]);
+        assert_eq!(rewind.restore(&mut simulation, 20), Some(20));
+        assert!(simulation.save_state() == states[20]);
+
+        // A buffer without capacity keeps nothing
+        let mut disabled = RewindBuffer::new(0, 1);
+        disabled.capture(&simulation);
+        assert_eq!(disabled.oldest(), None);
+    }
+}
@@ -0,0 +1,171 @@
+use serde::{Deserialize, Serialize};
+
+/// How a schedule interpolates between its keyframes
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Interpolation {
+    Linear,
+    /// Eases in and out of every keyframe
+    Smoothstep,
+    /// A smooth curve through the keyframes, a monotone cubic Hermite
+    /// spline which does not overshoot them
+    Cubic,
+}
+
+impl Interpolation {
+    /// Get an interpolation by its name, linear, smoothstep or cubic
+    pub fn from_name(name: &str) -> Option<Interpolation> {
+        return match name {
+            "linear" => Some(Interpolation::Linear),
+            "smoothstep" => Some(Interpolation::Smoothstep),
+            "cubic" => Some(Interpolation::Cubic),
+            _ => None,
+        };
+    }
+}
+
+/// Values of a parameter over the iterations of a simulation, given by
+/// keyframes. The value before the first and after the last keyframe
+/// is that of the keyframe.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Schedule {
+    pub interpolation: Interpolation,
+    // Iteration and value, sorted by iteration
+    keyframes: Vec<(usize, f64)>,
+}
+
+/// Serialized form of a schedule, which is checked before it is used
+#[derive(Deserialize)]
+struct ScheduleData {
+    interpolation: Interpolation,
+    keyframes: Vec<(usize, f64)>,
+}
+
+impl<'de> Deserialize<'de> for Schedule {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Schedule, D::Error> {
+        let data = ScheduleData::deserialize(deserializer)?;
+        let schedule = Schedule { interpolation: data.interpolation, keyframes: data.keyframes };
+        if !schedule.is_valid() {
+            return Err(serde::de::Error::custom("schedule keyframes are not sorted or not finite"));
+        }
+        return Ok(schedule);
+    }
+}
+
+impl Schedule {
+    pub fn new(interpolation: Interpolation) -> Schedule {
+        return Schedule { interpolation, keyframes: Vec::new() };
+    }
+
+    /// Create a schedule from keyframes in any order
+    pub fn from_keyframes(interpolation: Interpolation, keyframes: &[(usize, f64)]) -> Schedule {
+        let mut schedule = Schedule::new(interpolation);
+        for (iteration, value) in keyframes {
+            schedule.add_keyframe(*iteration, *value);
+        }
+        return schedule;
+    }
+
+    /// Add a keyframe, replacing any keyframe at the same iteration
+    pub fn add_keyframe(&mut self, iteration: usize, value: f64) {
+        match self.keyframes.binary_search_by_key(&iteration, |(i, _)| *i) {
+            Ok(index) => self.keyframes[index].1 = value,
+            Err(index) => self.keyframes.insert(index, (iteration, value)),
+        }
+    }
+
+    pub fn keyframes(&self) -> &[(usize, f64)] {
+        return &self.keyframes;
+    }
+
+    /// Are the keyframes at increasing iterations, with finite values?
+    /// Always true for keyframes added through `add_keyframe` with finite values.
+    pub fn is_valid(&self) -> bool {
+        return self.keyframes.windows(2).all(|pair| pair[0].0 < pair[1].0)
+            && self.keyframes.iter().all(|(_, value)| value.is_finite());
+    }
+
+    /// Get the value at an iteration, or `None` if there are no keyframes
+    pub fn evaluate(&self, iteration: usize) -> Option<f64> {
+        let next = self.keyframes.partition_point(|(i, _)| *i <= iteration);
+        if next == 0 {
+            return self.keyframes.first().map(|(_, value)| *value);
+        }
+        if next == self.keyframes.len() {
+            return self.keyframes.last().map(|(_, value)| *value);
+        }
+        let (t0, v0) = self.keyframes[next - 1];
+        let (t1, v1) = self.keyframes[next];
+        let t = (iteration - t0) as f64 / (t1 - t0) as f64;
+        return Some(match self.interpolation {
+            Interpolation::Linear => v0 + (v1 - v0) * t,
+            Interpolation::Smoothstep => v0 + (v1 - v0) * t * t * (3.0 - 2.0 * t),
+            Interpolation::Cubic => {
+                let (m0, m1) = (self.tangent(next - 1), self.tangent(next));
+                let h = (t1 - t0) as f64;
+                let (t2, t3) = (t * t, t * t * t);
+                (2.0 * t3 - 3.0 * t2 + 1.0) * v0 + (t3 - 2.0 * t2 + t) * h * m0
+                    + (-2.0 * t3 + 3.0 * t2) * v1 + (t3 - t2) * h * m1
+            }
+        });
+    }
+
+    /// Get the slope of the cubic spline at a keyframe, chosen so that
+    /// the curve stays monotone between keyframes
+    fn tangent(&self, index: usize) -> f64 {
+        let slope = |a: usize| {
+            let ((t0, v0), (t1, v1)) = (self.keyframes[a], self.keyframes[a + 1]);
+            return (v1 - v0) / (t1 - t0) as f64;
+        };
+        if index == 0 || index + 1 == self.keyframes.len() {
+            // Flat ends
+            return 0.0;
+        }
+        let (before, after) = (slope(index - 1), slope(index));
+        if before * after <= 0.0 {
+            // Extremum at the keyframe
+            return 0.0;
+        }
+        // The harmonic mean of the slopes on either side is at most twice
+        // the smaller one, which is within the monotone limit of three
+        return 2.0 / (1.0 / before + 1.0 / after);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedule() {
+        let keyframes = [(100, 0.4), (0, 0.3), (200, 0.4), (300, 0.1)];
+        for interpolation in [Interpolation::Linear, Interpolation::Smoothstep, Interpolation::Cubic] {
+            let schedule = Schedule::from_keyframes(interpolation, &keyframes);
+            // Keyframes and the values around them
+            assert_eq!(schedule.evaluate(0), Some(0.3));
+            assert_eq!(schedule.evaluate(100), Some(0.4));
+            assert_eq!(schedule.evaluate(1000), Some(0.1));
+            assert!((schedule.evaluate(150).unwrap() - 0.4).abs() < 1e-12);
+            let value = schedule.evaluate(50).unwrap();
+            assert!(value > 0.3 && value < 0.4);
+            // Never overshoots the keyframes
+            for i in 0..300 {
+                let value = schedule.evaluate(i).unwrap();
+                assert!(value > 0.1 - 1e-12 && value < 0.4 + 1e-12);
+            }
+        }
+        let linear = Schedule::from_keyframes(Interpolation::Linear, &keyframes);
+        assert!((linear.evaluate(250).unwrap() - 0.25).abs() < 1e-12);
+        assert_eq!(Schedule::new(Interpolation::Cubic).evaluate(10), None);
+
+        // Decoded keyframes must be sorted without duplicates, as evaluating
+        // them would underflow or divide by zero otherwise
+        let decode = |keyframes: &[(usize, f64)]| {
+            let bytes = bincode::serialize(&(Interpolation::Cubic, keyframes)).unwrap();
+            return bincode::deserialize::<Schedule>(&bytes);
+        };
+        assert_eq!(decode(&[(10, 0.3), (100, 0.4), (200, 0.1)]).unwrap().evaluate(100), Some(0.4));
+        assert!(decode(&[(100, 0.3), (10, 0.4), (200, 0.1)]).is_err());
+        assert!(decode(&[(10, 0.3), (10, 0.4)]).is_err());
+        assert!(decode(&[(10, f64::NAN)]).is_err());
+    }
+}
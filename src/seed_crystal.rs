@@ -0,0 +1,278 @@
+use super::hex_symmetry::{cube_length, cube_to_offset, offset_to_cube, Cube};
+
+use oorandom::Rand64;
+use serde::{Deserialize, Serialize};
+
+/// A bitmap of cells, run-length encoded in row order.
+/// Used to store crystals compactly.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CellMask {
+    width: usize,
+    height: usize,
+    // Lengths of alternating runs of unset and set cells, starting with
+    // unset cells. Cells after the last run are unset.
+    runs: Vec<u32>,
+}
+
+impl CellMask {
+    /// Create a mask without any set cells
+    pub fn new(width: usize, height: usize) -> CellMask {
+        return CellMask { width, height, runs: Vec::new() };
+    }
+
+    /// Create a mask of the cells for which `is_set` returns true
+    pub fn from_fn(width: usize, height: usize, is_set: impl Fn(usize, usize) -> bool) -> CellMask {
+        let mut mask = CellMask::new(width, height);
+        let (mut set, mut run) = (false, 0);
+        for y in 0..height {
+            for x in 0..width {
+                if is_set(x, y) != set {
+                    mask.runs.push(run);
+                    set = !set;
+                    run = 0;
+                }
+                run += 1;
+            }
+        }
+        if set {
+            mask.runs.push(run);
+        }
+        return mask;
+    }
+
+    /// Create a mask from a list of set cells, skipping cells outside of it
+    pub fn from_cells(width: usize, height: usize, cells: &[(usize, usize)]) -> CellMask {
+        let mut indices: Vec<usize> = cells.iter()
+            .filter(|(x, y)| *x < width && *y < height)
+            .map(|(x, y)| y * width + x)
+            .collect();
+        indices.sort_unstable();
+        indices.dedup();
+        let mut mask = CellMask::new(width, height);
+        let mut end = 0;
+        for index in indices {
+            if index == end && !mask.runs.is_empty() {
+                *mask.runs.last_mut().unwrap() += 1;
+            } else {
+                mask.runs.push((index - end) as u32);
+                mask.runs.push(1);
+            }
+            end = index + 1;
+        }
+        return mask;
+    }
+
+    /// Create a mask of the bright or opaque pixels of a PNG image,
+    /// with one cell per pixel
+    pub fn from_png(data: &[u8]) -> Result<CellMask, png::DecodingError> {
+        let mut decoder = png::Decoder::new(data);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info()?;
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let frame = reader.next_frame(&mut pixels)?;
+        let channels = frame.color_type.samples();
+        let color_channels = if channels >= 3 { 3 } else { 1 };
+        let has_alpha = channels == 2 || channels == 4;
+        let (width, height) = (frame.width as usize, frame.height as usize);
+        return Ok(CellMask::from_fn(width, height, |x, y| {
+            let pixel = &pixels[y * frame.line_size + x * channels..][..channels];
+            let brightness = pixel[..color_channels].iter().map(|c| *c as u32).sum::<u32>() / color_channels as u32;
+            let opaque = !has_alpha || pixel[channels - 1] >= 128;
+            return opaque && brightness >= 128;
+        }));
+    }
+
+    pub fn width(&self) -> usize {
+        return self.width;
+    }
+
+    pub fn height(&self) -> usize {
+        return self.height;
+    }
+
+    /// Do the runs fit within the size of the mask?
+    pub fn is_valid(&self) -> bool {
+        let total: u64 = self.runs.iter().map(|run| *run as u64).sum();
+        return total <= (self.width as u64).saturating_mul(self.height as u64);
+    }
+
+    /// Get the positions of the set cells in row order
+    pub fn cells(&self) -> Vec<(usize, usize)> {
+        let mut cells = Vec::new();
+        let mut i = 0;
+        for (index, run) in self.runs.iter().enumerate() {
+            let end = (i + *run as usize).min(self.width.saturating_mul(self.height));
+            if index % 2 == 1 {
+                cells.extend((i..end).map(|i| (i % self.width, i / self.width)));
+            }
+            i = end;
+        }
+        return cells;
+    }
+}
+
+/// Shapes of seed crystals, which are placed as frozen cells.
+/// Positions are cells of the simulation grid, and cells of a shape
+/// outside of the grid are skipped.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SeedShape {
+    /// A single cell
+    Single { x: usize, y: usize },
+    /// A filled hexagon of the cells within `radius` steps of the center
+    Hexagon { x: usize, y: usize, radius: usize },
+    /// A needle of `length` cells centered on a cell, along one of the
+    /// three axes of the grid. Axis 0 is horizontal, axes 1 and 2 are
+    /// rotated by 60 and 120 degrees.
+    Line { x: usize, y: usize, length: usize, axis: usize },
+    /// A ring of the cells exactly `radius` steps from the center
+    Ring { x: usize, y: usize, radius: usize },
+    /// `count` nuclei at random cells within `radius` steps of the center,
+    /// placed using their own random seed
+    Scatter { x: usize, y: usize, radius: usize, count: usize, seed: u64 },
+    /// The set cells of a mask, centered on a cell
+    Mask { x: usize, y: usize, mask: CellMask },
+}
+
+impl SeedShape {
+    /// Get the cell the shape is centered on
+    pub fn center(&self) -> (usize, usize) {
+        return match self {
+            SeedShape::Single { x, y }
+            | SeedShape::Hexagon { x, y, .. }
+            | SeedShape::Line { x, y, .. }
+            | SeedShape::Ring { x, y, .. }
+            | SeedShape::Scatter { x, y, .. }
+            | SeedShape::Mask { x, y, .. } => (*x, *y),
+        };
+    }
+
+    /// Get the cells of the shape within a grid
+    pub fn cells(&self, width: usize, height: usize) -> Vec<(usize, usize)> {
+        // No cell of the grid is further than this from any cell, which
+        // limits the work for shapes of untrusted sizes
+        let max_radius = width + height;
+        let mut cells = Vec::new();
+        let mut add = |(x, y): (isize, isize)| {
+            if x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height {
+                cells.push((x as usize, y as usize));
+            }
+        };
+        match self {
+            SeedShape::Single { x, y } => add((*x as isize, *y as isize)),
+            SeedShape::Hexagon { x, y, radius } => {
+                for offset in hexagon_offsets((*radius).min(max_radius)) {
+                    add(offset_cell(*x, *y, offset));
+                }
+            }
+            SeedShape::Ring { x, y, radius } => {
+                let radius = (*radius).min(max_radius);
+                for offset in hexagon_offsets(radius).filter(|offset| cube_length(*offset) == radius as isize) {
+                    add(offset_cell(*x, *y, offset));
+                }
+            }
+            SeedShape::Line { x, y, length, axis } => {
+                let (dx, dy, dz) = [(1, -1, 0), (1, 0, -1), (0, 1, -1)][axis % 3];
+                let length = (*length).min(2 * max_radius + 1) as isize;
+                for k in (0..length).map(|k| k - (length - 1) / 2) {
+                    add(offset_cell(*x, *y, (k * dx, k * dy, k * dz)));
+                }
+            }
+            SeedShape::Scatter { x, y, radius, count, seed } => {
+                let mut rand = Rand64::new(*seed as u128);
+                let r = (*radius).min(max_radius) as isize;
+                let mut placed = 0;
+                while placed < (*count).min(width * height) {
+                    // Uniform within the hexagon by rejecting samples outside of it
+                    let a = rand.rand_range(0..(2 * r + 1) as u64) as isize - r;
+                    let c = rand.rand_range(0..(2 * r + 1) as u64) as isize - r;
+                    if (a + c).abs() <= r {
+                        add(offset_cell(*x, *y, (a, -a - c, c)));
+                        placed += 1;
+                    }
+                }
+            }
+            SeedShape::Mask { x, y, mask } => {
+                let (x0, y0) = (*x as isize - (mask.width / 2) as isize, *y as isize - (mask.height / 2) as isize);
+                for (mx, my) in mask.cells() {
+                    add((x0 + mx as isize, y0 + my as isize));
+                }
+            }
+        }
+        return cells;
+    }
+}
+
+/// Get the cube offsets of every cell within `radius` steps of a cell
+fn hexagon_offsets(radius: usize) -> impl Iterator<Item = Cube> {
+    let r = radius as isize;
+    return (-r..=r).flat_map(move |a| (-r..=r).filter(move |c| (a + c).abs() <= r).map(move |c| (a, -a - c, c)));
+}
+
+/// Get the cell at a cube offset from a cell
+fn offset_cell(x: usize, y: usize, offset: Cube) -> (isize, isize) {
+    // Cube coordinates use the padded layout
+    let (cx, cy, cz) = offset_to_cube(x as isize + 1, y as isize + 1);
+    let (px, py) = cube_to_offset((cx + offset.0, cy + offset.1, cz + offset.2));
+    return (px - 1, py - 1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::sim::get_neighbours;
+
+    #[test]
+    fn test_seed_shapes() {
+        let count = |shape: SeedShape| shape.cells(100, 100).len();
+        assert_eq!(count(SeedShape::Single { x: 50, y: 50 }), 1);
+        assert_eq!(count(SeedShape::Hexagon { x: 50, y: 51, radius: 4 }), 3 * 4 * 5 + 1);
+        assert_eq!(count(SeedShape::Ring { x: 50, y: 51, radius: 4 }), 6 * 4);
+        assert_eq!(count(SeedShape::Line { x: 50, y: 50, length: 7, axis: 1 }), 7);
+        // Shapes are clipped to the grid
+        assert_eq!(count(SeedShape::Hexagon { x: 0, y: 50, radius: 1 }), 4);
+
+        // A radius 1 hexagon is a cell and its neighbours
+        for (x, y) in [(50, 50), (50, 51)] {
+            let mut hexagon = SeedShape::Hexagon { x, y, radius: 1 }.cells(100, 100);
+            let mut expected: Vec<(usize, usize)> = get_neighbours(x as isize + 1, y as isize + 1).iter()
+                .map(|(nx, ny)| (*nx as usize - 1, *ny as usize - 1))
+                .chain([(x, y)])
+                .collect();
+            hexagon.sort();
+            expected.sort();
+            assert_eq!(hexagon, expected);
+        }
+
+        // Lines are made of neighbouring cells
+        for axis in 0..3 {
+            let line = SeedShape::Line { x: 50, y: 50, length: 5, axis }.cells(100, 100);
+            for pair in line.windows(2) {
+                let (x, y) = pair[0];
+                assert!(get_neighbours(x as isize + 1, y as isize + 1).contains(&(pair[1].0 as isize + 1, pair[1].1 as isize + 1)));
+            }
+        }
+
+        let scatter = SeedShape::Scatter { x: 50, y: 50, radius: 10, count: 20, seed: 3 };
+        assert_eq!(scatter.cells(100, 100), scatter.cells(100, 100));
+        assert!(scatter.cells(100, 100).iter().all(|(x, y)| x.abs_diff(50) <= 10 && y.abs_diff(50) <= 10));
+    }
+
+    #[test]
+    fn test_cell_mask() {
+        let mask = CellMask::from_fn(7, 5, |x, y| (x + y) % 3 == 0 || y == 4);
+        assert!(mask.is_valid());
+        let expected: Vec<(usize, usize)> = (0..5).flat_map(|y| (0..7).map(move |x| (x, y)))
+            .filter(|(x, y)| (x + y) % 3 == 0 || *y == 4)
+            .collect();
+        assert_eq!(mask.cells(), expected);
+        assert_eq!(CellMask::from_fn(7, 5, |_, _| false).runs.len(), 0);
+        let mut shuffled = expected.clone();
+        shuffled.reverse();
+        shuffled.push((7, 0));
+        assert_eq!(CellMask::from_cells(7, 5, &shuffled), mask);
+
+        let shape = SeedShape::Mask { x: 20, y: 20, mask };
+        assert_eq!(shape.cells(100, 100).len(), expected.len());
+        assert_eq!(shape.cells(100, 100)[0], (17, 18));
+    }
+}
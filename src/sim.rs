@@ -1,15 +1,120 @@
+use super::active_region::Region;
+use super::growth_model::GrowthModel;
+use super::hex_symmetry::SymmetryDomain;
+use super::noise::{splitmix64, NoiseField};
+use super::schedule::Schedule;
+use super::termination::{GrowthChanges, GrowthStatus, GrowthTracker, StopConditions};
 use oorandom::Rand64;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
 
+/// Name of the model, see `growth_model::create_model`
+pub const MODEL_NAME: &str = "reiter";
+static PARAM_NAMES: [&str; 6] = ["alpha", "beta", "gamma", "alpha_rand", "beta_rand", "gamma_rand"];
 static RANDOM_BUFFER_SIZE: usize = 10000;
 static RAND_SEED: u64 = 34917983469832;
 
+/// How the random numbers of parameter randomization are generated
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RandomMode {
+    /// Read numbers in stepping order from a buffer of 10,000 numbers,
+    /// which repeats. Simulations from before `Counter` use this mode.
+    Buffer,
+    /// Hash the seed, iteration and cell index, which never repeats
+    /// and does not depend on the order cells are stepped in
+    Counter,
+}
+
+impl RandomMode {
+    /// Get a random mode by its name, buffer or counter
+    pub fn from_name(name: &str) -> Option<RandomMode> {
+        return match name {
+            "buffer" => Some(RandomMode::Buffer),
+            "counter" => Some(RandomMode::Counter),
+            _ => None,
+        };
+    }
+}
+
+/// How the edge of the grid interacts with the vapor around the crystal.
+/// Symmetric mode always uses the outermost ring of its hexagon as the edge.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Boundary {
+    /// The outermost ring of the grid is held at the background vapor
+    Fixed,
+    /// The cells outside of the largest circle around the center of the
+    /// grid are held at the background vapor, so large crystals do not
+    /// feel the corners of the grid. Distances are measured between the
+    /// centers of the hexagons.
+    Circular,
+    /// Vapor reaching the outermost ring of the grid is absorbed by the
+    /// far field, the background vapor only fills the grid at the start
+    Absorbing,
+    /// Vapor leaving one side of the grid enters on the opposite side.
+    /// Only possible on grids with an even height, see `fits_height`.
+    Periodic,
+    /// No vapor crosses the edge of the grid, as if it was mirrored
+    Reflecting,
+}
+
+impl Boundary {
+    /// Get a boundary by its name, fixed, circular, absorbing, periodic or reflecting
+    pub fn from_name(name: &str) -> Option<Boundary> {
+        return match name {
+            "fixed" => Some(Boundary::Fixed),
+            "circular" => Some(Boundary::Circular),
+            "absorbing" => Some(Boundary::Absorbing),
+            "periodic" => Some(Boundary::Periodic),
+            "reflecting" => Some(Boundary::Reflecting),
+            _ => None,
+        };
+    }
+
+    /// Can the boundary be used on a grid of this height? The shifted rows
+    /// of a periodic grid only line up across the edge when the height is
+    /// even, otherwise neighbours across the edge would be one-sided.
+    pub fn fits_height(&self, height: usize) -> bool {
+        return *self != Boundary::Periodic || height.is_multiple_of(2);
+    }
+}
+
+/// The randomized parameter a random number is used for, so that the
+/// parameters of a cell get independent numbers in counter mode.
+/// Also indexes the noise field of the parameter.
+#[derive(Clone, Copy)]
+enum RandomStream {
+    VaporAddition = 0,
+    VaporDiffusion = 1,
+    BackgroundVapor = 2,
+}
+
 /// Represents a single hexagonal cell of the simulation
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Cell {
     water: f64,
     receptive: bool,
 }
 
+/// Full state of a simulation, see `GrowthModel::save_state`
+#[derive(Serialize, Deserialize)]
+struct SimState {
+    current: Vec<Cell>,
+    next: Vec<Cell>,
+    // Parameter values in the order of `PARAM_NAMES`
+    params: Vec<f64>,
+    seed: u64,
+    random_mode: RandomMode,
+    random_buffer_index: usize,
+    noise_fields: [Option<NoiseField>; 3],
+    schedules: Vec<(String, Schedule)>,
+    boundary: Boundary,
+    // Padded center of symmetric mode
+    symmetry_center: Option<(usize, usize)>,
+    stop_conditions: StopConditions,
+    growth: GrowthTracker,
+    iteration_count: usize,
+}
+
 /// Represents a Snowflake Simulation based on
 /// Reiters model,
 /// see http://www.patarnott.com/pdf/SnowCrystalGrowth.pdf for
@@ -25,9 +130,44 @@ pub struct SnowflakeSim {
     rwidth: usize,
     rheight: usize,
 
-    // Random buffer
+    // Random buffer, only used in buffer mode
     random_buffer: Vec<f64>,
     random_buffer_index: usize,
+    random_mode: RandomMode,
+
+    // Noise fields modulating gamma, alpha and beta per cell,
+    // indexed by `RandomStream`
+    noise_fields: [Option<NoiseField>; 3],
+
+    // Parameters which follow a schedule, set at the start of every step
+    schedules: Vec<(String, Schedule)>,
+
+    // Edge of the grid, and the cells set every step by it
+    boundary: Boundary,
+    reservoir: Vec<usize>,
+
+    // Symmetric mode, only the fundamental wedge is stepped
+    symmetry: Option<SymmetryDomain>,
+
+    // When the crystal has finished growing, measured every step
+    // while any condition is enabled
+    stop_conditions: StopConditions,
+    growth: GrowthTracker,
+    // Steps from every padded cell to the edge, computed when needed
+    edge_distances: Vec<usize>,
+
+    // Active region, only cells whose surroundings changed in the last
+    // iteration are stepped. Holds the bounding box of the cells which
+    // changed, or `None` if unknown.
+    active_region: Option<Region>,
+    // Parameters (alpha, beta, gamma) the active region was computed with
+    active_region_params: (f64, f64, f64),
+    /// Only step the region around the crystal when this gives
+    /// the same result as stepping every cell
+    pub use_active_region: bool,
+    /// Step rows using multiple threads
+    #[cfg(feature = "parallel")]
+    pub parallel: bool,
 
     // Simulation parameters
     /// Alpha
@@ -45,6 +185,7 @@ pub struct SnowflakeSim {
 }
 
 impl SnowflakeSim {
+    #[allow(clippy::redundant_field_names)]
     pub fn new(width: usize, height: usize, alpha: f64, beta: f64, gamma: f64) -> SnowflakeSim {
         let mut sim = SnowflakeSim {
             current: vec![
@@ -73,6 +214,20 @@ impl SnowflakeSim {
             vapor_addition_rand: 0.0,
             random_buffer: vec![0.0; RANDOM_BUFFER_SIZE],
             random_buffer_index: 0,
+            random_mode: RandomMode::Counter,
+            noise_fields: [None; 3],
+            schedules: Vec::new(),
+            boundary: Boundary::Fixed,
+            reservoir: reservoir_cells(Boundary::Fixed, width, height),
+            symmetry: None,
+            stop_conditions: StopConditions::default(),
+            growth: GrowthTracker::default(),
+            edge_distances: Vec::new(),
+            active_region: None,
+            active_region_params: (alpha, beta, gamma),
+            use_active_region: true,
+            #[cfg(feature = "parallel")]
+            parallel: true,
             seed : 0,
             iteration_count: 0,
         };
@@ -83,11 +238,19 @@ impl SnowflakeSim {
     }
 
     /// Set the water level of a cell. Useful for initial setup of the
-    /// seed crystal.
+    /// seed crystal. In symmetric mode, all 12 symmetric cells are set.
+    #[allow(clippy::assign_op_pattern)]
     pub fn set_water(&mut self, mut x: usize, mut y: usize, val: f64) {
         // Adjust for padding manually
         x = x + 1;
         y = y + 1;
+        if let Some(symmetry) = &self.symmetry {
+            let i = symmetry.map[y * self.rwidth + x];
+            x = i % self.rwidth;
+            y = i / self.rwidth;
+        }
+        self.active_region = None;
+        self.growth.invalidate();
         self.current[y * self.rwidth + x].water = val;
         if val >= 1.0 {
             // This cell is now frozen, we have to do
@@ -96,14 +259,106 @@ impl SnowflakeSim {
             self.next[y * self.rwidth + x].receptive = true;
             let neighbour_coords = get_neighbours(x as isize, y as isize);
             for (nx, ny) in neighbour_coords {
-                if self.is_within_bounds(nx, ny) {
-                    self.current[(ny as usize) * self.rwidth + (nx as usize)].receptive = true;
-                    self.next[(ny as usize) * self.rwidth + (nx as usize)].receptive = true;
+                if let Some(ni) = self.neighbour_index(nx, ny) {
+                    let ni = self.map_index(ni);
+                    self.current[ni].receptive = true;
+                    self.next[ni].receptive = true;
                 }
             }
         }
     }
 
+    /// Enable symmetric mode, where only a 1/12 wedge around the center
+    /// cell is simulated and the rest of the grid is mirrored from it.
+    ///
+    /// The simulated domain is the largest hexagon around the center which
+    /// fits in the grid, with its outermost ring acting as the edge.
+    /// The crystal will only stay symmetric if the randomization
+    /// parameters are zero. If the current state is not symmetric,
+    /// the state of the wedge is used.
+    ///
+    /// * `cx`, `cy` - position of the center cell, usually the seed crystal
+    pub fn enable_symmetry(&mut self, cx: usize, cy: usize) {
+        self.disable_symmetry();
+        self.symmetry = Some(SymmetryDomain::new(self.rwidth, self.rheight, cx + 1, cy + 1));
+        self.edge_distances.clear();
+    }
+
+    /// Disable symmetric mode and mirror the wedge into the whole grid
+    pub fn disable_symmetry(&mut self) {
+        self.active_region = None;
+        self.edge_distances.clear();
+        self.growth.invalidate();
+        if let Some(symmetry) = self.symmetry.take() {
+            for i in 0..self.current.len() {
+                self.current[i] = self.current[symmetry.map[i]];
+                self.next[i] = self.next[symmetry.map[i]];
+            }
+        }
+    }
+
+    /// Is symmetric mode enabled?
+    pub fn is_symmetric(&self) -> bool {
+        return self.symmetry.is_some();
+    }
+
+    /// Get the position of the center cell of symmetric mode, if enabled
+    pub fn symmetry_center(&self) -> Option<(usize, usize)> {
+        return self.symmetry.as_ref().map(|symmetry| (symmetry.center.0 - 1, symmetry.center.1 - 1));
+    }
+
+    pub fn boundary(&self) -> Boundary {
+        return self.boundary;
+    }
+
+    /// Set how the edge of the grid interacts with the vapor.
+    /// Returns false if the boundary does not fit the grid, see `Boundary::fits_height`.
+    pub fn set_boundary(&mut self, boundary: Boundary) -> bool {
+        if !boundary.fits_height(self.height) {
+            return false;
+        }
+        self.active_region = None;
+        self.boundary = boundary;
+        self.reservoir = reservoir_cells(boundary, self.width, self.height);
+        self.edge_distances.clear();
+        self.growth.invalidate();
+        return true;
+    }
+
+    pub fn stop_conditions(&self) -> StopConditions {
+        return self.stop_conditions;
+    }
+
+    /// Set when the crystal has finished growing, see `status`.
+    /// Stalls are counted from the current iteration.
+    pub fn set_stop_conditions(&mut self, conditions: StopConditions) {
+        self.stop_conditions = conditions;
+        self.update_edge_distances();
+        let edge_distances = &self.edge_distances;
+        let rwidth = self.rwidth;
+        self.growth = GrowthTracker::new(self, &|x, y| edge_distances[(y + 1) * rwidth + x + 1]);
+    }
+
+    /// Get the measurements the stop conditions are checked against
+    pub fn growth(&self) -> &GrowthTracker {
+        return &self.growth;
+    }
+
+    /// Get the first stop condition the simulation has met,
+    /// or `GrowthStatus::Growing` if there is none
+    pub fn status(&self) -> GrowthStatus {
+        return self.growth.status(&self.stop_conditions, self.iteration_count);
+    }
+
+    /// Map a padded index to the index holding its state. In symmetric mode
+    /// this is the representative in the fundamental wedge.
+    fn map_index(&self, i: usize) -> usize {
+        return match &self.symmetry {
+            Some(symmetry) => symmetry.map[i],
+            None => i,
+        };
+    }
+
     pub fn set_random_seed(&mut self, seed : u64) {
         let mut rand = Rand64::new(seed as u128);
         for i in 0..self.random_buffer.len() {
@@ -112,35 +367,132 @@ impl SnowflakeSim {
         self.seed = seed;
     }
 
-    fn get_background_vapor(&mut self) -> f64 {
+    pub fn random_mode(&self) -> RandomMode {
+        return self.random_mode;
+    }
+
+    /// Set how random numbers are generated. New simulations use
+    /// counter mode, buffer mode reproduces older simulations.
+    pub fn set_random_mode(&mut self, mode: RandomMode) {
+        self.random_mode = mode;
+    }
+
+    /// Set a noise field modulating a parameter per cell, or remove it.
+    /// The field is seeded by the random seed of the simulation.
+    ///
+    /// * `param` - alpha, beta or gamma
+    ///
+    /// Returns false if there is no such parameter or the field is invalid,
+    /// see `NoiseField::is_valid`.
+    pub fn set_noise_field(&mut self, param: &str, field: Option<NoiseField>) -> bool {
+        let stream = match noise_stream(param) {
+            Some(stream) => stream,
+            None => return false,
+        };
+        if field.is_some_and(|field| !field.is_valid()) {
+            return false;
+        }
+        self.noise_fields[stream as usize] = field;
+        return true;
+    }
+
+    /// Get the noise field modulating a parameter, if any
+    pub fn noise_field(&self, param: &str) -> Option<NoiseField> {
+        return noise_stream(param).and_then(|stream| self.noise_fields[stream as usize]);
+    }
+
+    /// Get the scheduled parameters with their schedules
+    pub fn schedules(&self) -> &[(String, Schedule)] {
+        return &self.schedules;
+    }
+
+    /// Make a parameter follow a schedule over the iterations, or stop
+    /// following one. A scheduled parameter is set at the start of every
+    /// step, which overrides setting it directly.
+    ///
+    /// * `param` - name of any parameter, see `GrowthModel::param_names`
+    ///
+    /// Returns false if there is no such parameter or the schedule is
+    /// invalid, see `Schedule::is_valid`.
+    pub fn set_schedule(&mut self, param: &str, schedule: Option<Schedule>) -> bool {
+        if !PARAM_NAMES.contains(&param) || schedule.as_ref().is_some_and(|schedule| !schedule.is_valid()) {
+            return false;
+        }
+        self.schedules.retain(|(name, _)| name != param);
+        if let Some(schedule) = schedule {
+            self.schedules.push((param.to_string(), schedule));
+        }
+        self.apply_schedules();
+        return true;
+    }
+
+    /// Set the scheduled parameters to their values at the current iteration
+    fn apply_schedules(&mut self) {
+        for i in 0..self.schedules.len() {
+            let (name, schedule) = &self.schedules[i];
+            if let Some(value) = schedule.evaluate(self.iteration_count) {
+                // Setting beta before the first iteration refills the
+                // background, so only set changed values
+                if self.get_param(name) != Some(value) {
+                    let name = name.clone();
+                    self.set_param(&name, value);
+                }
+            }
+        }
+    }
+
+    /// * `i` - index of the edge cell
+    fn get_background_vapor(&mut self, i: usize) -> f64 {
+        let value = self.get_noise_param(self.background_vapor, RandomStream::BackgroundVapor, i);
         if self.background_vapor_rand > 0.0 {
-            return self.background_vapor * self.get_random_factor(self.background_vapor_rand);
+            return value * self.get_random_factor(self.background_vapor_rand, RandomStream::BackgroundVapor, i);
         }
         else {
-            return self.background_vapor;
+            return value;
         }
     }
 
-    fn get_vapor_diffusion(&mut self) -> f64 {
+    /// * `i` - index of the stepped cell
+    fn get_vapor_diffusion(&mut self, i: usize) -> f64 {
+        let value = self.get_noise_param(self.vapor_diffusion, RandomStream::VaporDiffusion, i);
         if self.vapor_diffusion_rand > 0.0 {
-            return self.vapor_diffusion * self.get_random_factor(self.vapor_diffusion_rand);
+            return value * self.get_random_factor(self.vapor_diffusion_rand, RandomStream::VaporDiffusion, i);
         }
         else {
-            return self.vapor_diffusion;
+            return value;
         }
     }
 
-    fn get_vapor_addition(&mut self) -> f64 {
+    /// * `i` - index of the stepped cell
+    fn get_vapor_addition(&mut self, i: usize) -> f64 {
+        let value = self.get_noise_param(self.vapor_addition, RandomStream::VaporAddition, i);
         if self.vapor_addition_rand > 0.0 {
             // Randomize by a factor of the random vapor addition param
-            return self.vapor_addition * self.get_random_factor(self.vapor_addition_rand);
+            return value * self.get_random_factor(self.vapor_addition_rand, RandomStream::VaporAddition, i);
         }
         else {
-            return self.vapor_addition;
+            return value;
         }
     }
 
+    /// Get a parameter modulated by its noise field at a cell, if it has one
+    fn get_noise_param(&self, value: f64, stream: RandomStream, i: usize) -> f64 {
+        return match &self.noise_fields[stream as usize] {
+            Some(field) => {
+                // Sample at the center of the hexagon, so the field is not stretched
+                let (x, y) = (i % self.rwidth, i / self.rwidth);
+                let px = x as f64 + 0.5 * (y % 2) as f64;
+                let py = y as f64 * 3f64.sqrt() / 2.0;
+                let seed = splitmix64(self.seed).wrapping_add(stream as u64 * 1000);
+                value * field.factor(seed, px, py, self.iteration_count as f64)
+            }
+            None => value,
+        };
+    }
+
     pub fn fill_starting_background_vapor(&mut self) {
+        self.active_region = None;
+        self.growth.invalidate();
         for i in 0..self.current.len() {
             if self.current[i].water < 1.0 {
                 self.current[i].water = self.background_vapor;
@@ -160,43 +512,242 @@ impl SnowflakeSim {
 
     /// Get a random number mapped between (1 - rand_range, 1 + rand_range),
     /// with negative values clamped to 0.
-    fn get_random_factor(&mut self, rand_range : f64) -> f64 {
-        // Random number mapped between (1 - rand_range, 1 + rand_range)
-        // Clamp to 0 to prevent negative values
-        return (0.0 as f64).max(1.0 + rand_range * (1.0 - self.get_next_rand() * 2.0));
+    ///
+    /// * `stream`, `i` - the parameter and the index of the cell the number
+    ///   is for, which only matter in counter mode
+    fn get_random_factor(&mut self, rand_range : f64, stream: RandomStream, i: usize) -> f64 {
+        let rand = match self.random_mode {
+            RandomMode::Buffer => self.get_next_rand(),
+            RandomMode::Counter => counter_rand(self.seed, self.iteration_count, i, stream),
+        };
+        return random_factor(rand_range, rand);
     }
 
 
     /// Get the water level of a cell.
+    #[allow(clippy::assign_op_pattern)]
     pub fn get_water(&self, mut x: usize, mut y: usize) -> f64 {
         // Adjust for padding manually
         x = x + 1;
         y = y + 1;
-        return self.current[y * self.rwidth + x].water;
+        return self.current[self.map_index(y * self.rwidth + x)].water;
     }
 
     /// Step the Reiters Model simulation one iteration.
     pub fn step(&mut self) {
-        // Step all cells
-        for y in 1..self.height + 1 {
-            for x in 1..self.width + 1 {
-                self.step_cell(x, y);
-            }
+        self.apply_schedules();
+        if self.symmetry.is_some() {
+            self.step_symmetric();
+            self.update_growth(&Region::empty());
+            return;
         }
 
-        // Loop over edge cells and introduce water to the system
-        for y in 1..self.height + 1 {
-            self.next[y * self.rwidth + 1].water = self.get_background_vapor();
-            self.next[y * self.rwidth + self.width].water = self.get_background_vapor();
+        self.fill_padding();
+        let params = (self.vapor_diffusion, self.background_vapor, self.vapor_addition);
+        if !self.can_use_active_region() || params != self.active_region_params {
+            self.active_region = None;
+        }
+        // Step the cells next to those which changed, or all cells if unknown
+        let stepped = match self.active_region {
+            Some(region) => region.grow(1, self.width, self.height),
+            None => Region::full(self.width, self.height),
+        };
+        if !stepped.is_empty() {
+            self.step_region(&stepped);
         }
-        for x in 1..self.width + 1 {
-            self.next[1 * self.rwidth + x].water = self.get_background_vapor();
-            self.next[self.height * self.rwidth + x].water = self.get_background_vapor();
+
+        // Loop over the reservoir cells and introduce water to the system
+        for k in 0..self.reservoir.len() {
+            let i = self.reservoir[k];
+            self.next[i].water = match self.boundary {
+                Boundary::Absorbing => 0.0,
+                _ => self.get_background_vapor(i),
+            };
         }
 
         // Swap current and next
         std::mem::swap(&mut self.current, &mut self.next);
         self.iteration_count += 1;
+
+        self.update_active_region(&stepped);
+        self.update_growth(&stepped);
+    }
+
+    /// Measure the crystal for the stop conditions after stepping, from
+    /// the cells which were stepped
+    ///
+    /// * `stepped` - the region which was stepped, unused in symmetric
+    ///   mode where the wedge was stepped
+    fn update_growth(&mut self, stepped: &Region) {
+        if !self.stop_conditions.is_enabled() {
+            return;
+        }
+        self.update_edge_distances();
+        let mut changes = GrowthChanges::default();
+        // The previous state of the stepped cells is in the next buffer after swapping
+        let mut compare = |i: usize, cells: &[usize]| {
+            let (before, after) = (self.next[i].water, self.current[i].water);
+            if before < 1.0 && after < 1.0 {
+                return;
+            }
+            let mass = (if after >= 1.0 { after } else { 0.0 }) - (if before >= 1.0 { before } else { 0.0 });
+            changes.mass += mass * cells.len() as f64;
+            if before < 1.0 {
+                changes.frozen.extend(cells.iter().map(|c| (c % self.rwidth - 1, c / self.rwidth - 1)));
+            } else if after < 1.0 {
+                changes.thawed = true;
+            }
+        };
+        match &self.symmetry {
+            Some(symmetry) => {
+                for (&i, orbit) in symmetry.cells.iter().zip(&symmetry.orbits) {
+                    compare(i, orbit);
+                }
+            }
+            None if !stepped.is_empty() => {
+                for y in stepped.y0..stepped.y1 + 1 {
+                    for x in stepped.x0..stepped.x1 + 1 {
+                        let i = y * self.rwidth + x;
+                        compare(i, &[i]);
+                    }
+                }
+            }
+            None => {}
+        }
+
+        let mut growth = self.growth;
+        let edge_distances = &self.edge_distances;
+        growth.update(self, &changes, &|x, y| edge_distances[(y + 1) * self.rwidth + x + 1]);
+        self.growth = growth;
+    }
+
+    /// Compute the steps from every padded cell to the closest cell holding
+    /// the edge of the simulation, if the edge changed. The edge is the
+    /// padding ring and the reservoir cells, or the outermost ring of the
+    /// hexagon in symmetric mode.
+    fn update_edge_distances(&mut self) {
+        if !self.edge_distances.is_empty() {
+            return;
+        }
+        let mut edge = vec![false; self.current.len()];
+        for (i, is_edge) in edge.iter_mut().enumerate() {
+            *is_edge = !self.is_within_bounds((i % self.rwidth) as isize, (i / self.rwidth) as isize);
+        }
+        match &self.symmetry {
+            Some(symmetry) => {
+                let mut ring = vec![false; self.current.len()];
+                for &i in &symmetry.edge {
+                    ring[i] = true;
+                }
+                for (i, is_edge) in edge.iter_mut().enumerate() {
+                    *is_edge |= ring[symmetry.map[i]];
+                }
+            }
+            None => {
+                for &i in &self.reservoir {
+                    edge[i] = true;
+                }
+            }
+        }
+
+        let mut distances = vec![usize::MAX; self.current.len()];
+        let mut queue = VecDeque::new();
+        for i in (0..edge.len()).filter(|i| edge[*i]) {
+            distances[i] = 0;
+            queue.push_back(i);
+        }
+        while let Some(i) = queue.pop_front() {
+            for (nx, ny) in get_neighbours((i % self.rwidth) as isize, (i / self.rwidth) as isize) {
+                if nx < 0 || ny < 0 || nx >= self.rwidth as isize || ny >= self.rheight as isize {
+                    continue;
+                }
+                let n = (ny as usize) * self.rwidth + nx as usize;
+                if distances[n] == usize::MAX {
+                    distances[n] = distances[i] + 1;
+                    queue.push_back(n);
+                }
+            }
+        }
+        self.edge_distances = distances;
+    }
+
+    /// Step all cells within a region, using multiple threads
+    /// if the `parallel` feature is enabled
+    fn step_region(&mut self, region: &Region) {
+        #[cfg(feature = "parallel")]
+        if self.parallel {
+            self.step_region_parallel(region);
+            return;
+        }
+
+        for y in region.y0..region.y1 + 1 {
+            for x in region.x0..region.x1 + 1 {
+                let map = self.neighbour_map(y * self.rwidth + x);
+                self.step_cell(x, y, &map);
+            }
+        }
+    }
+
+    /// Can the active region be used? A cell whose neighbourhood did not
+    /// change keeps its value, as long as stepping is deterministic.
+    fn can_use_active_region(&self) -> bool {
+        return self.use_active_region
+            && self.vapor_diffusion_rand == 0.0
+            && self.background_vapor_rand == 0.0
+            && self.vapor_addition_rand == 0.0
+            && self.noise_fields.iter().all(|field| field.is_none())
+            // Changes at one edge reach the opposite edge
+            && self.boundary != Boundary::Periodic;
+    }
+
+    /// Update the active region after stepping
+    ///
+    /// * `stepped` - the region which was stepped
+    fn update_active_region(&mut self, stepped: &Region) {
+        if !self.can_use_active_region() {
+            self.active_region = None;
+            return;
+        }
+        // Cells next to the stepped region can have been marked as receptive
+        let scanned = stepped.grow(1, self.width, self.height);
+        let mut region = Region::empty();
+        if !scanned.is_empty() {
+            for y in scanned.y0..scanned.y1 + 1 {
+                for x in scanned.x0..scanned.x1 + 1 {
+                    let cell = self.current[y * self.rwidth + x];
+                    let previous_cell = self.next[y * self.rwidth + x];
+                    if cell.water.to_bits() != previous_cell.water.to_bits()
+                        || cell.receptive != previous_cell.receptive
+                    {
+                        region.include(x, y);
+                    }
+                }
+            }
+        }
+        self.active_region = Some(region);
+        self.active_region_params = (self.vapor_diffusion, self.background_vapor, self.vapor_addition);
+    }
+
+    /// Step only the fundamental wedge of the simulation one iteration.
+    /// Neighbours outside of the wedge are read from their mirrored
+    /// representatives inside the wedge.
+    fn step_symmetric(&mut self) {
+        let symmetry = self.symmetry.take().unwrap();
+        // The hexagon of the domain replaces the boundary of the grid
+        let boundary = std::mem::replace(&mut self.boundary, Boundary::Fixed);
+        for &i in &symmetry.cells {
+            self.step_cell(i % self.rwidth, i / self.rwidth, &|n| symmetry.map[n]);
+        }
+
+        // The outermost ring of the hexagon acts as the edge
+        for &i in &symmetry.edge {
+            self.next[i].water = self.get_background_vapor(i);
+        }
+
+        std::mem::swap(&mut self.current, &mut self.next);
+        self.iteration_count += 1;
+        self.symmetry = Some(symmetry);
+        self.boundary = boundary;
     }
 
     /// Is a position within bounds of the simulation?
@@ -204,50 +755,429 @@ impl SnowflakeSim {
         return x >= 1 && x <= self.width as isize && y >= 1 && y <= self.height as isize;
     }
 
+    /// Get the padded index of the cell at a position next to a cell of
+    /// the grid, which wraps around to the opposite edge for periodic
+    /// boundaries. Returns `None` if the position is outside of the grid.
+    fn neighbour_index(&self, x: isize, y: isize) -> Option<usize> {
+        if self.is_within_bounds(x, y) {
+            return Some((y as usize) * self.rwidth + x as usize);
+        }
+        if self.boundary == Boundary::Periodic {
+            let x = wrap(x, self.width);
+            let y = wrap(y, self.height);
+            return Some(y * self.rwidth + x);
+        }
+        return None;
+    }
+
+    /// Fill the padding ring around the grid with the opposite edges for
+    /// periodic boundaries. Other boundaries keep the background vapor.
+    fn fill_padding(&mut self) {
+        if self.boundary != Boundary::Periodic {
+            return;
+        }
+        let (rwidth, rheight) = (self.rwidth as isize, self.rheight as isize);
+        let padding = (0..rwidth).flat_map(|x| [(x, 0), (x, rheight - 1)])
+            .chain((1..rheight - 1).flat_map(|y| [(0, y), (rwidth - 1, y)]));
+        for (x, y) in padding {
+            let i = (y as usize) * self.rwidth + x as usize;
+            self.current[i] = self.current[wrap(y, self.height) * self.rwidth + wrap(x, self.width)];
+        }
+    }
+
+    /// Get the map from the neighbours of a cell to the cells holding their
+    /// state. With reflecting boundaries, neighbours outside of the grid hold
+    /// the state of the cell itself, so no vapor flows across the edge.
+    fn neighbour_map(&self, i: usize) -> impl Fn(usize) -> usize {
+        let reflecting = self.boundary == Boundary::Reflecting;
+        let (rwidth, width, height) = (self.rwidth, self.width, self.height);
+        return move |n| {
+            let (x, y) = (n % rwidth, n / rwidth);
+            if reflecting && (x < 1 || x > width || y < 1 || y > height) {
+                return i;
+            }
+            return n;
+        };
+    }
+
     /// Step a single cell for one iteration
-    fn step_cell(&mut self, x: usize, y: usize) {
-        let cell: Cell = self.current[y * self.rwidth + x];
-        let mut next_cell = self.next[y * self.rwidth + x];
+    ///
+    /// * `map` - maps the index of a neighbour to the index holding its state
+    fn step_cell(&mut self, x: usize, y: usize, map: &impl Fn(usize) -> usize) {
+        let i = y * self.rwidth + x;
+        let receptive = self.current[i].receptive;
+        let vapor_addition = if receptive { self.get_vapor_addition(i) } else { 0.0 };
+        let vapor_diffusion = self.get_vapor_diffusion(i);
+        let (next_cell, changed_frozen) = compute_cell(
+            &self.current,
+            self.rwidth,
+            x,
+            y,
+            self.next[y * self.rwidth + x],
+            vapor_addition,
+            vapor_diffusion,
+            map,
+        );
+        if changed_frozen {
+            self.mark_neighbours_receptive(x, y, map);
+        }
 
-        let mut diff_particip: f64 = 0.0;
-        let mut diff_nonparticip: f64 = 0.0;
+        self.next[y * self.rwidth + x] = next_cell;
+    }
 
-        if cell.receptive {
-            diff_nonparticip = cell.water + self.get_vapor_addition();
-        } else {
-            diff_particip = cell.water;
+    /// If a cell was just frozen, we need to update the neighbours as
+    /// receptive in the next state
+    fn mark_neighbours_receptive(&mut self, x: usize, y: usize, map: &impl Fn(usize) -> usize) {
+        for (nx, ny) in get_neighbours(x as isize, y as isize) {
+            if let Some(ni) = self.neighbour_index(nx, ny) {
+                self.next[map(ni)].receptive = true;
+            }
         }
+    }
 
-        // Count the average water content among the neighbours
-        let mut water_avg: f64 = 0.0;
+    /// Step the rows of a region using multiple threads.
+    ///
+    /// Every row reads random numbers from the position it would have in a
+    /// single threaded step, and freezing cells mark their neighbours as
+    /// receptive after all rows are done, so the result is the same as
+    /// stepping the cells one by one.
+    #[cfg(feature = "parallel")]
+    fn step_region_parallel(&mut self, region: &Region) {
+        use rayon::prelude::*;
 
-        let neighbour_coords = get_neighbours(x as isize, y as isize);
-        for (nx, ny) in neighbour_coords {
-            let neighbour = self.current[((ny) as usize) * self.rwidth + nx as usize];
-            if !neighbour.receptive {
-                water_avg += neighbour.water;
+        let rwidth = self.rwidth;
+        let (x0, x1) = (region.x0, region.x1);
+        // Find where each row starts in the random buffer
+        let addition_rand = self.vapor_addition_rand > 0.0 && self.random_mode == RandomMode::Buffer;
+        let diffusion_rand = self.vapor_diffusion_rand > 0.0 && self.random_mode == RandomMode::Buffer;
+        let mut row_rand_index = Vec::with_capacity(region.y1 + 1 - region.y0);
+        for y in region.y0..region.y1 + 1 {
+            row_rand_index.push(self.random_buffer_index);
+            for x in x0..x1 + 1 {
+                if addition_rand && self.current[y * rwidth + x].receptive {
+                    self.random_buffer_index += 1;
+                }
+                if diffusion_rand {
+                    self.random_buffer_index += 1;
+                }
             }
         }
 
-        water_avg = water_avg / 6.0;
+        let mut next = std::mem::take(&mut self.next);
+        let sim = &*self;
+        let changed_frozen: Vec<Vec<usize>> = next[region.y0 * rwidth..(region.y1 + 1) * rwidth]
+            .par_chunks_mut(rwidth)
+            .zip(row_rand_index.par_iter())
+            .enumerate()
+            .map(|(row, (next_row, &rand_index))| {
+                let y = region.y0 + row;
+                let mut rand_index = rand_index;
+                let mut changed_frozen = Vec::new();
+                for (x, next_cell) in next_row.iter_mut().enumerate().take(x1 + 1).skip(x0) {
+                    let i = y * rwidth + x;
+                    let vapor_addition = if sim.current[i].receptive {
+                        let stream = (RandomStream::VaporAddition, i);
+                        let value = sim.get_noise_param(sim.vapor_addition, RandomStream::VaporAddition, i);
+                        sim.get_rand_param(value, sim.vapor_addition_rand, stream, &mut rand_index)
+                    } else {
+                        0.0
+                    };
+                    let stream = (RandomStream::VaporDiffusion, i);
+                    let value = sim.get_noise_param(sim.vapor_diffusion, RandomStream::VaporDiffusion, i);
+                    let vapor_diffusion = sim.get_rand_param(value, sim.vapor_diffusion_rand, stream, &mut rand_index);
+                    let (cell, changed) =
+                        compute_cell(&sim.current, rwidth, x, y, *next_cell, vapor_addition, vapor_diffusion, &sim.neighbour_map(i));
+                    *next_cell = cell;
+                    if changed {
+                        changed_frozen.push(x);
+                    }
+                }
+                return changed_frozen;
+            })
+            .collect();
+        self.next = next;
 
-        // Diffuse
-        diff_particip = diff_particip + (self.get_vapor_diffusion() / 2.0) * (water_avg - diff_particip);
+        for (row, xs) in changed_frozen.iter().enumerate() {
+            for &x in xs {
+                self.mark_neighbours_receptive(x, region.y0 + row, &|i| i);
+            }
+        }
+    }
 
-        let started_frozen = next_cell.water >= 1.0;
-        next_cell.water = diff_particip + diff_nonparticip;
-        let ended_frozen = next_cell.water >= 1.0;
-        if started_frozen != ended_frozen {
-            // If this cell was just frozen, we need to update the neighbours as
-            // receptive
-            for (nx, ny) in neighbour_coords {
-                if self.is_within_bounds(nx, ny) {
-                    self.next[(ny as usize) * self.rwidth + (nx as usize)].receptive = true;
+    /// Get a randomized parameter. In buffer mode the random number at
+    /// `rand_index` is used, which is advanced if a random number was used.
+    /// In counter mode the number of the `stream` and cell index is used.
+    #[cfg(feature = "parallel")]
+    fn get_rand_param(&self, value: f64, rand_range: f64, (stream, i): (RandomStream, usize), rand_index: &mut usize) -> f64 {
+        if rand_range > 0.0 {
+            let rand = match self.random_mode {
+                RandomMode::Buffer => {
+                    *rand_index += 1;
+                    self.random_buffer[(*rand_index - 1) % RANDOM_BUFFER_SIZE]
                 }
+                RandomMode::Counter => counter_rand(self.seed, self.iteration_count, i, stream),
+            };
+            return value * random_factor(rand_range, rand);
+        }
+        else {
+            return value;
+        }
+    }
+}
+
+/// Compute the next state of a single cell from the current state.
+/// Returns the next state and whether the cell was frozen or unfrozen.
+///
+/// * `next_cell` - the state of the cell in the next buffer
+/// * `vapor_addition`, `vapor_diffusion` - the parameters used for this cell
+/// * `map` - maps the index of a neighbour to the index holding its state
+#[allow(clippy::too_many_arguments)]
+fn compute_cell(
+    current: &[Cell],
+    rwidth: usize,
+    x: usize,
+    y: usize,
+    mut next_cell: Cell,
+    vapor_addition: f64,
+    vapor_diffusion: f64,
+    map: &impl Fn(usize) -> usize,
+) -> (Cell, bool) {
+    let cell: Cell = current[y * rwidth + x];
+
+    let mut diff_particip: f64 = 0.0;
+    let mut diff_nonparticip: f64 = 0.0;
+
+    if cell.receptive {
+        diff_nonparticip = cell.water + vapor_addition;
+    } else {
+        diff_particip = cell.water;
+    }
+
+    // Count the average water content among the neighbours
+    let mut water_avg: f64 = 0.0;
+
+    let neighbour_coords = get_neighbours(x as isize, y as isize);
+    for (nx, ny) in neighbour_coords {
+        let neighbour = current[map(((ny) as usize) * rwidth + nx as usize)];
+        if !neighbour.receptive {
+            water_avg += neighbour.water;
+        }
+    }
+
+    water_avg /= 6.0;
+
+    // Diffuse
+    diff_particip = diff_particip + (vapor_diffusion / 2.0) * (water_avg - diff_particip);
+
+    let started_frozen = next_cell.water >= 1.0;
+    next_cell.water = diff_particip + diff_nonparticip;
+    let ended_frozen = next_cell.water >= 1.0;
+    return (next_cell, started_frozen != ended_frozen);
+}
+
+/// Map a padded coordinate of the padding ring to the opposite
+/// edge of the grid, coordinates within the grid are kept
+fn wrap(i: isize, size: usize) -> usize {
+    return (i - 1).rem_euclid(size as isize) as usize + 1;
+}
+
+/// Get the padded indices of the cells a boundary sets every step,
+/// in the order they are set
+fn reservoir_cells(boundary: Boundary, width: usize, height: usize) -> Vec<usize> {
+    let rwidth = width + 2;
+    let mut cells = Vec::new();
+    match boundary {
+        Boundary::Fixed | Boundary::Absorbing => {
+            // The order, including the corners set twice, keeps the
+            // random numbers of the random buffer in place
+            for y in 1..height + 1 {
+                cells.extend([y * rwidth + 1, y * rwidth + width]);
+            }
+            for x in 1..width + 1 {
+                cells.extend([rwidth + x, height * rwidth + x]);
             }
         }
+        Boundary::Circular => {
+            // Center of the hexagon of a padded cell, even padded rows are shifted
+            let center = |x: usize, y: usize| (x as f64 + 0.5 * ((y + 1) % 2) as f64, y as f64 * 3f64.sqrt() / 2.0);
+            let (cx, cy) = center(width / 2 + 1, height / 2 + 1);
+            let (_, y0) = center(1, 1);
+            let (_, y1) = center(width, height);
+            let radius = (cx - 1.0).min(width as f64 - cx).min(cy - y0).min(y1 - cy);
+            // The padding ring lies outside of the circle as well
+            for y in 0..height + 2 {
+                for x in 0..width + 2 {
+                    let (px, py) = center(x, y);
+                    if (px - cx).hypot(py - cy) >= radius {
+                        cells.push(y * rwidth + x);
+                    }
+                }
+            }
+        }
+        Boundary::Periodic | Boundary::Reflecting => {}
+    }
+    return cells;
+}
 
-        self.next[y * self.rwidth + x] = next_cell;
+/// Get a random number between 0 and 1 for a parameter of a cell at an
+/// iteration, by hashing them together with the seed
+fn counter_rand(seed: u64, iteration: usize, i: usize, stream: RandomStream) -> f64 {
+    let hash = splitmix64(splitmix64(splitmix64(seed) ^ iteration as u64) ^ ((i as u64) << 2 | stream as u64));
+    // The top 53 bits fill the mantissa
+    return (hash >> 11) as f64 / (1u64 << 53) as f64;
+}
+
+/// Get the random stream of a parameter which can have a noise field
+fn noise_stream(param: &str) -> Option<RandomStream> {
+    return match param {
+        "alpha" => Some(RandomStream::VaporDiffusion),
+        "beta" => Some(RandomStream::BackgroundVapor),
+        "gamma" => Some(RandomStream::VaporAddition),
+        _ => None,
+    };
+}
+
+/// Map a random number between 0 and 1 to a factor between
+/// (1 - rand_range, 1 + rand_range), with negative values clamped to 0.
+fn random_factor(rand_range: f64, rand: f64) -> f64 {
+    // Clamp to 0 to prevent negative values
+    return 0.0f64.max(1.0 + rand_range * (1.0 - rand * 2.0));
+}
+
+impl GrowthModel for SnowflakeSim {
+    fn model_name(&self) -> &'static str {
+        return MODEL_NAME;
+    }
+
+    fn width(&self) -> usize {
+        return self.width;
+    }
+
+    fn height(&self) -> usize {
+        return self.height;
+    }
+
+    fn iteration_count(&self) -> usize {
+        return self.iteration_count;
+    }
+
+    fn step(&mut self) {
+        SnowflakeSim::step(self);
+    }
+
+    fn get_water(&self, x: usize, y: usize) -> f64 {
+        return SnowflakeSim::get_water(self, x, y);
+    }
+
+    fn set_water(&mut self, x: usize, y: usize, val: f64) {
+        SnowflakeSim::set_water(self, x, y, val);
+    }
+
+    fn param_names(&self) -> &'static [&'static str] {
+        return &PARAM_NAMES;
+    }
+
+    fn get_param(&self, name: &str) -> Option<f64> {
+        return match name {
+            "alpha" => Some(self.vapor_diffusion),
+            "beta" => Some(self.background_vapor),
+            "gamma" => Some(self.vapor_addition),
+            "alpha_rand" => Some(self.vapor_diffusion_rand),
+            "beta_rand" => Some(self.background_vapor_rand),
+            "gamma_rand" => Some(self.vapor_addition_rand),
+            _ => None,
+        };
+    }
+
+    fn set_param(&mut self, name: &str, value: f64) -> bool {
+        match name {
+            "alpha" => self.vapor_diffusion = value,
+            "beta" => {
+                self.background_vapor = value;
+                if self.iteration_count == 0 {
+                    // We need to update the starting background vapor
+                    self.fill_starting_background_vapor();
+                }
+            }
+            "gamma" => self.vapor_addition = value,
+            "alpha_rand" => self.vapor_diffusion_rand = value,
+            "beta_rand" => self.background_vapor_rand = value,
+            "gamma_rand" => self.vapor_addition_rand = value,
+            _ => return false,
+        }
+        return true;
+    }
+
+    fn seed(&self) -> u64 {
+        return self.seed;
+    }
+
+    fn set_random_seed(&mut self, seed: u64) {
+        SnowflakeSim::set_random_seed(self, seed);
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        let state = SimState {
+            current: self.current.clone(),
+            next: self.next.clone(),
+            params: PARAM_NAMES.iter().map(|name| self.get_param(name).unwrap()).collect(),
+            seed: self.seed,
+            random_mode: self.random_mode,
+            random_buffer_index: self.random_buffer_index,
+            noise_fields: self.noise_fields,
+            schedules: self.schedules.clone(),
+            boundary: self.boundary,
+            symmetry_center: self.symmetry.as_ref().map(|symmetry| symmetry.center),
+            stop_conditions: self.stop_conditions,
+            growth: self.growth,
+            iteration_count: self.iteration_count,
+        };
+        return bincode::serialize(&state).unwrap();
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> bool {
+        let state: SimState = match bincode::deserialize(state) {
+            Ok(state) => state,
+            Err(_) => return false,
+        };
+        if state.current.len() != self.current.len() || state.next.len() != self.next.len()
+            || state.params.len() != PARAM_NAMES.len()
+            || !state.boundary.fits_height(self.height)
+            || state.noise_fields.iter().flatten().any(|field| !field.is_valid())
+            || state.symmetry_center.is_some_and(|(cx, cy)| cx >= self.rwidth || cy >= self.rheight) {
+            return false;
+        }
+        // Parameters first, as they can refill the cells
+        for (name, value) in PARAM_NAMES.iter().zip(&state.params) {
+            self.set_param(name, *value);
+        }
+        if state.seed != self.seed {
+            self.set_random_seed(state.seed);
+        }
+        self.random_mode = state.random_mode;
+        self.random_buffer_index = state.random_buffer_index;
+        self.noise_fields = state.noise_fields;
+        self.schedules = state.schedules;
+        self.set_boundary(state.boundary);
+        if self.symmetry.as_ref().map(|symmetry| symmetry.center) != state.symmetry_center {
+            self.symmetry = state.symmetry_center.map(|(cx, cy)| SymmetryDomain::new(self.rwidth, self.rheight, cx, cy));
+            self.edge_distances.clear();
+        }
+        self.stop_conditions = state.stop_conditions;
+        self.growth = state.growth;
+        self.current = state.current;
+        self.next = state.next;
+        self.iteration_count = state.iteration_count;
+        self.active_region = None;
+        return true;
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        return self;
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        return self;
     }
 }
 
@@ -266,7 +1196,7 @@ impl std::fmt::Display for SnowflakeSim {
 
 /// Get an array of the 6 neighbour coordinates. These can be
 /// out of bounds.
-fn get_neighbours(x: isize, y: isize) -> [(isize, isize); 6] {
+pub(crate) fn get_neighbours(x: isize, y: isize) -> [(isize, isize); 6] {
     return if y % 2 == 1 {
         [
             (x + 1, y),
@@ -316,4 +1246,205 @@ mod tests {
             elapsed.div_f64(ITERATIONS as f64)
         );
     }
+
+    #[test]
+    fn test_symmetric_sim() {
+        let (cx, cy) = (GRID_WIDTH / 2, GRID_HEIGHT / 2);
+        let mut full = SnowflakeSim::new(GRID_WIDTH, GRID_HEIGHT, 1.0, 0.4, 0.0001);
+        full.set_water(cx, cy, 1.0);
+        let mut symmetric = SnowflakeSim::new(GRID_WIDTH, GRID_HEIGHT, 1.0, 0.4, 0.0001);
+        symmetric.enable_symmetry(cx, cy);
+        symmetric.set_water(cx, cy, 1.0);
+        assert!(symmetric.is_symmetric());
+
+        for _ in 0..ITERATIONS {
+            full.step();
+            symmetric.step();
+        }
+
+        // The edges differ between the modes, but the crystal in the center
+        // should match up to floating point summation order
+        for y in cy - 30..cy + 30 {
+            for x in cx - 30..cx + 30 {
+                assert!((full.get_water(x, y) - symmetric.get_water(x, y)).abs() < 1e-9);
+            }
+        }
+
+        // Mirroring the wedge into the grid keeps the state
+        let water = symmetric.get_water(cx + 5, cy + 7);
+        symmetric.disable_symmetry();
+        assert_eq!(symmetric.get_water(cx + 5, cy + 7), water);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_sim() {
+        let cases = [
+            (RandomMode::Buffer, Boundary::Fixed),
+            (RandomMode::Counter, Boundary::Periodic),
+            (RandomMode::Counter, Boundary::Reflecting),
+        ];
+        for (mode, boundary) in cases {
+            let mut serial = SnowflakeSim::new(GRID_WIDTH, GRID_HEIGHT, 1.0, 0.4, 0.0001);
+            serial.parallel = false;
+            let mut parallel = SnowflakeSim::new(GRID_WIDTH, GRID_HEIGHT, 1.0, 0.4, 0.0001);
+            for sim in [&mut serial, &mut parallel] {
+                sim.set_random_mode(mode);
+                sim.set_boundary(boundary);
+                sim.set_water(GRID_WIDTH / 2, GRID_HEIGHT / 2, 1.0);
+                sim.vapor_diffusion_rand = 0.3;
+                sim.vapor_addition_rand = 0.5;
+                sim.vapor_addition = 0.001;
+            }
+
+            for _ in 0..ITERATIONS {
+                serial.step();
+                parallel.step();
+            }
+
+            // Multithreaded stepping must give the exact same result
+            for y in 0..GRID_HEIGHT {
+                for x in 0..GRID_WIDTH {
+                    assert_eq!(serial.get_water(x, y).to_bits(), parallel.get_water(x, y).to_bits());
+                }
+            }
+            assert_eq!(serial.random_buffer_index, parallel.random_buffer_index);
+        }
+    }
+
+    #[test]
+    fn test_counter_random() {
+        // Numbers never repeat with a period, unlike the random buffer
+        let mut numbers: Vec<u64> = Vec::new();
+        for iteration in [0, 1, 10_000] {
+            for i in 0..GRID_WIDTH * GRID_HEIGHT {
+                for stream in [RandomStream::VaporAddition, RandomStream::VaporDiffusion] {
+                    let rand = counter_rand(RAND_SEED, iteration, i, stream);
+                    assert!((0.0..1.0).contains(&rand));
+                    numbers.push(rand.to_bits());
+                }
+            }
+        }
+        let count = numbers.len();
+        let mean = numbers.iter().map(|bits| f64::from_bits(*bits)).sum::<f64>() / count as f64;
+        assert!((mean - 0.5).abs() < 0.01);
+        numbers.sort_unstable();
+        numbers.dedup();
+        assert_eq!(numbers.len(), count);
+
+        // Stepping the same simulation twice gives the same result,
+        // while another seed gives a different one
+        let mut sims: Vec<SnowflakeSim> = (0..3).map(|_| SnowflakeSim::new(50, 50, 1.0, 0.4, 0.001)).collect();
+        sims[2].set_random_seed(7);
+        for sim in &mut sims {
+            sim.vapor_addition_rand = 0.5;
+            sim.set_water(25, 25, 1.0);
+            for _ in 0..50 {
+                sim.step();
+            }
+        }
+        assert_eq!(sims[0].get_water(26, 25).to_bits(), sims[1].get_water(26, 25).to_bits());
+        assert_ne!(sims[0].get_water(26, 25).to_bits(), sims[2].get_water(26, 25).to_bits());
+    }
+
+    #[test]
+    fn test_active_region() {
+        for boundary in [Boundary::Fixed, Boundary::Circular, Boundary::Absorbing, Boundary::Reflecting] {
+            let mut full = SnowflakeSim::new(GRID_WIDTH, GRID_HEIGHT, 1.0, 0.4, 0.0001);
+            full.use_active_region = false;
+            let mut active = SnowflakeSim::new(GRID_WIDTH, GRID_HEIGHT, 1.0, 0.4, 0.0001);
+            for sim in [&mut full, &mut active] {
+                sim.set_boundary(boundary);
+                sim.set_water(GRID_WIDTH / 2, GRID_HEIGHT / 2, 1.0);
+            }
+
+            for i in 0..ITERATIONS {
+                if i == 50 {
+                    full.vapor_addition = 0.001;
+                    active.vapor_addition = 0.001;
+                }
+                full.step();
+                active.step();
+                if i == 10 && boundary != Boundary::Absorbing {
+                    // Only the region around the crystal should be stepped
+                    let region = active.active_region.unwrap();
+                    assert!(region.x1 - region.x0 < GRID_WIDTH / 2);
+                }
+            }
+
+            // Stepping only the active region must match stepping every cell exactly
+            for y in 0..GRID_HEIGHT {
+                for x in 0..GRID_WIDTH {
+                    assert_eq!(full.get_water(x, y).to_bits(), active.get_water(x, y).to_bits());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_boundaries() {
+        let (width, height) = (40, 30);
+        let total = |sim: &SnowflakeSim| (0..height).flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| sim.get_water(x, y))
+            .sum::<f64>();
+
+        // Periodic boundaries make the grid a torus, so moving the crystal
+        // moves the whole state, even across the edges
+        let mut sims: Vec<SnowflakeSim> = (0..2).map(|_| SnowflakeSim::new(width, height, 1.0, 0.4, 0.001)).collect();
+        let (dx, dy) = (17, 12);
+        sims[0].set_water(2, 3, 1.0);
+        sims[1].set_water(2 + dx, 3 + dy, 1.0);
+        for sim in &mut sims {
+            sim.set_boundary(Boundary::Periodic);
+            for _ in 0..300 {
+                sim.step();
+            }
+        }
+        assert!(sims[0].get_water(width - 1, 3) >= 1.0);
+        for y in 0..height {
+            for x in 0..width {
+                let moved = sims[1].get_water((x + dx) % width, (y + dy) % height);
+                assert_eq!(sims[0].get_water(x, y).to_bits(), moved.to_bits());
+            }
+        }
+
+        // Shifted rows only line up across the edge on grids with an even height
+        let mut sim = SnowflakeSim::new(width, height + 1, 1.0, 0.4, 0.001);
+        assert!(!sim.set_boundary(Boundary::Periodic));
+        assert_eq!(sim.boundary(), Boundary::Fixed);
+        let mut state = SnowflakeSim::new(width, height + 1, 1.0, 0.4, 0.001);
+        state.boundary = Boundary::Periodic;
+        assert!(!sim.load_state(&state.save_state()));
+
+        // Reflecting boundaries keep the vapor in the grid
+        let mut sim = SnowflakeSim::new(width, height, 1.0, 0.4, 0.001);
+        sim.set_boundary(Boundary::Reflecting);
+        sim.set_water(0, 0, 0.9);
+        sim.set_water(20, 29, 0.0);
+        let start = total(&sim);
+        for _ in 0..100 {
+            sim.step();
+        }
+        assert!((total(&sim) - start).abs() < 1e-9);
+        assert!(sim.get_water(0, 0) < 0.9);
+
+        // Absorbing boundaries drain the vapor, while the reservoir of
+        // circular boundaries keeps the corners at the background vapor
+        let mut absorbing = SnowflakeSim::new(width, height, 1.0, 0.4, 0.001);
+        absorbing.set_boundary(Boundary::Absorbing);
+        let mut circular = SnowflakeSim::new(width, height, 1.0, 0.4, 0.001);
+        circular.set_boundary(Boundary::Circular);
+        for sim in [&mut absorbing, &mut circular] {
+            sim.set_water(width / 2, height / 2, 1.0);
+            sim.set_param("beta", 0.5);
+            for _ in 0..100 {
+                sim.step();
+            }
+        }
+        assert_eq!(absorbing.get_water(0, height / 2), 0.0);
+        assert!(total(&absorbing) < 0.4 * (width * height) as f64);
+        assert_eq!(circular.get_water(0, 0), 0.5);
+        assert_eq!(circular.get_water(width - 1, height - 1), 0.5);
+        assert!(circular.get_water(width / 2, 2) < 0.5);
+    }
 }
\ No newline at end of file
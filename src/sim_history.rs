@@ -1,28 +1,177 @@
+use super::growth_model::{create_model, GrowthModel};
+use super::keyframes::Keyframes;
+use super::noise::NoiseField;
+use super::schedule::Schedule;
+use super::seed_crystal::{CellMask, SeedShape};
 use super::sim;
 
 use serde::{Serialize, Deserialize};
 use flate2::write::ZlibEncoder;
 use flate2::write::ZlibDecoder;
+use std::convert::TryInto;
+use std::fmt;
 use std::io::Write;
 
-/// Track the history of an attribute
-#[derive(Serialize, Deserialize)]
+/// Magic bytes at the start of a serialized history.
+/// Histories from before the format was versioned have no header.
+const FORMAT_MAGIC: &[u8; 4] = b"SNFL";
+/// Version of the serialized history format, written after the magic bytes.
+/// Version 1 stored attribute ticks as `u16`, histories before
+/// version 3 always used the random buffer of Reiters model,
+/// histories before version 4 stored the starting cells as a list and
+/// histories before version 5 stored every edit tick as a `u64`.
+const FORMAT_VERSION: u8 = 5;
+/// Largest grid a history or snapshot can describe, to reject corrupted sizes
+/// before allocating the simulation
+pub(crate) const MAX_GRID_CELLS: usize = 2048 * 2048;
+
+/// Errors from decoding a serialized simulation history
+#[derive(Debug)]
+pub enum HistoryError {
+    /// The string is not valid URL safe base64
+    Base64(base64::DecodeError),
+    /// The data could not be decompressed
+    Decompress(std::io::Error),
+    /// The decompressed data does not match the format
+    Decode(bincode::Error),
+    /// The history was written by a newer format version
+    UnsupportedVersion(u8),
+    /// The history uses a growth model which does not exist
+    UnknownModel(String),
+    /// The history decoded, but describes an impossible simulation
+    InvalidData(String),
+}
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            HistoryError::Base64(err) => write!(f, "invalid base64 in simulation history: {}", err),
+            HistoryError::Decompress(err) => write!(f, "could not decompress simulation history: {}", err),
+            HistoryError::Decode(err) => write!(f, "could not decode simulation history: {}", err),
+            HistoryError::UnsupportedVersion(version) => {
+                write!(f, "simulation history format version {} is not supported, the latest is {}", version, FORMAT_VERSION)
+            }
+            HistoryError::UnknownModel(model) => write!(f, "unknown growth model '{}' in simulation history", model),
+            HistoryError::InvalidData(reason) => write!(f, "invalid simulation history: {}", reason),
+        };
+    }
+}
+
+impl std::error::Error for HistoryError {}
+
+/// Track the history of an attribute.
+/// Serialized as varint encoded tick deltas followed by the values,
+/// which keeps histories of arbitrarily long runs compact.
 struct AttribHistory {
+    history : Vec<(usize, f64)>
+}
+
+/// Attribute history of format version 1 and earlier,
+/// which wraps after 65,535 ticks
+#[derive(Deserialize)]
+struct U16AttribHistory {
     history : Vec<(u16, f64)>
 }
 
+/// A change to the simulation state made between iterations
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum Edit {
+    /// Set a named parameter of the model
+    Param(String, f64),
+    /// Set the random seed
+    Seed(u64),
+    /// Set the water level of a cell
+    Water(usize, usize, f64),
+    /// Enable symmetric mode around a center, or disable it
+    Symmetry(Option<(usize, usize)>),
+    /// Set how Reiters model generates random numbers
+    RandomMode(sim::RandomMode),
+    /// Set or remove the noise field of a parameter of Reiters model
+    Noise(String, Option<NoiseField>),
+    /// Set or remove the schedule of a parameter of Reiters model
+    Schedule(String, Option<Schedule>),
+    /// Place a seed crystal
+    Crystal(SeedShape),
+    /// Set the boundary of Reiters model
+    Boundary(sim::Boundary),
+}
+
+/// Is the history being recorded or played back?
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum HistoryState {
+    #[default]
+    Idle,
+    Tracking,
+    Playback,
+}
+
+/// The history format from before the format was versioned,
+/// which only supported Reiters model
+#[derive(Deserialize)]
+struct LegacyHistory {
+    alpha_history : U16AttribHistory,
+    beta_history: U16AttribHistory,
+    gamma_history: U16AttribHistory,
+    alpha_rand_history: U16AttribHistory,
+    seed: u64,
+    size: (usize, usize),
+    start_filled: Vec<(usize, usize)>,
+}
+
+/// History format version 1
+#[derive(Deserialize)]
+struct HistoryV1 {
+    model: String,
+    param_histories: Vec<(String, U16AttribHistory)>,
+    seed: u64,
+    size: (usize, usize),
+    start_filled: Vec<(usize, usize)>,
+    edits: Vec<(usize, Edit)>,
+}
+
+/// History format versions 2 and 3
+#[derive(Deserialize)]
+struct HistoryV3 {
+    model: String,
+    param_histories: Vec<(String, AttribHistory)>,
+    seed: u64,
+    size: (usize, usize),
+    start_filled: Vec<(usize, usize)>,
+    edits: Vec<(usize, Edit)>,
+}
+
+/// History format version 4
+#[derive(Deserialize)]
+struct HistoryV4 {
+    model: String,
+    param_histories: Vec<(String, AttribHistory)>,
+    seed: u64,
+    size: (usize, usize),
+    start_filled: CellMask,
+    edits: Vec<(usize, Edit)>,
+}
+
 /// Track the history of a Snowflake Simulation
 /// and allow for playback as well as
 /// saving the history state as a string.
 #[derive(Serialize, Deserialize)]
 pub struct SimStateHistory {
-    alpha_history : AttribHistory,
-    beta_history: AttribHistory,
-    gamma_history: AttribHistory,
-    alpha_rand_history: AttribHistory,
+    model: String,
+    // History of every named parameter of the model
+    param_histories: Vec<(String, AttribHistory)>,
     seed: u64,
     size: (usize, usize),
-    start_filled: Vec<(usize, usize)>,
+    // Frozen cells at the start
+    start_filled: CellMask,
+    // Edits in the order they were made, together with the iteration
+    // count they were made at. Applied before stepping that iteration.
+    #[serde(serialize_with = "serialize_edits", deserialize_with = "deserialize_edits")]
+    edits: Vec<(usize, Edit)>,
+    #[serde(skip)]
+    state: HistoryState,
+    // Checkpoints taken during playback, for seeking
+    #[serde(skip)]
+    keyframes: Keyframes,
 }
 
 impl AttribHistory {
@@ -31,20 +180,35 @@ impl AttribHistory {
     }
 
     /// Update the attribute for a certain time tick
+    #[allow(clippy::len_zero)]
     fn add(&mut self, tick: usize, value: f64) {
         // Only add to history if there was a change
         if self.history.len() == 0 || self.history.last().unwrap().1 != value {
-            self.history.push((tick as u16, value));
+            self.history.push((tick, value));
         }
     }
 
+    /// Get the value the attribute changed to at a certain time tick,
+    /// or `None` if it did not change
+    fn get_change(&self, tick: usize) -> Option<f64> {
+        return self.history.iter().rev().find(|(t, _)| *t == tick).map(|(_, value)| *value);
+    }
+
+    /// Remove the changes after a certain time tick. The first value is
+    /// always kept, as it is the starting value of the attribute.
+    fn truncate(&mut self, tick: usize) {
+        let end = self.history.partition_point(|(t, _)| *t <= tick).max(1);
+        self.history.truncate(end);
+    }
+
     /// Get the attribute for a certain time tick
+    #[allow(clippy::unnecessary_cast)]
     fn get(&self, tick: usize) -> f64 {
         let mut first = &self.history[0];
         for second in &self.history[1..] {
             // Find which two points the tick lies imbetween,
             // then break and return the value of the first point
-            if first.0 <= tick as u16 && second.0 > tick as u16 {
+            if first.0 <= tick && second.0 > tick {
                 break;
             }
             first = second;
@@ -57,102 +221,487 @@ impl AttribHistory {
     }
 }
 
+impl From<U16AttribHistory> for AttribHistory {
+    fn from(old: U16AttribHistory) -> AttribHistory {
+        return AttribHistory { history: old.history.iter().map(|(tick, value)| (*tick as usize, *value)).collect() };
+    }
+}
+
+impl Serialize for AttribHistory {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut bytes = Vec::new();
+        let mut previous = 0;
+        for (tick, value) in &self.history {
+            // Ticks are added in order, but keep any order decodable
+            write_varint(&mut bytes, tick.wrapping_sub(previous) as u64);
+            bytes.extend_from_slice(&value.to_le_bytes());
+            previous = *tick;
+        }
+        return bytes.serialize(serializer);
+    }
+}
+
+impl<'de> Deserialize<'de> for AttribHistory {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<AttribHistory, D::Error> {
+        let bytes: Vec<u8> = Deserialize::deserialize(deserializer)?;
+        let mut history = Vec::new();
+        let mut rest = &bytes[..];
+        let mut previous: usize = 0;
+        while !rest.is_empty() {
+            let delta = read_varint(&mut rest).ok_or_else(|| serde::de::Error::custom("invalid tick varint"))?;
+            if rest.len() < 8 {
+                return Err(serde::de::Error::custom("truncated attribute value"));
+            }
+            let (value, remaining) = rest.split_at(8);
+            rest = remaining;
+            previous = previous.wrapping_add(delta as usize);
+            history.push((previous, f64::from_le_bytes(value.try_into().unwrap())));
+        }
+        return Ok(AttribHistory { history });
+    }
+}
+
+/// Serialize edits as varint encoded tick deltas followed by the edits,
+/// which keeps the ticks of arbitrarily long runs compact
+fn serialize_edits<S: serde::Serializer>(edits: &[(usize, Edit)], serializer: S) -> Result<S::Ok, S::Error> {
+    let mut ticks = Vec::new();
+    let mut previous = 0;
+    for (tick, _) in edits {
+        // Ticks are recorded in order, but keep any order decodable
+        write_varint(&mut ticks, tick.wrapping_sub(previous) as u64);
+        previous = *tick;
+    }
+    let edits: Vec<&Edit> = edits.iter().map(|(_, edit)| edit).collect();
+    return (ticks, edits).serialize(serializer);
+}
+
+fn deserialize_edits<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<(usize, Edit)>, D::Error> {
+    let (ticks, edits): (Vec<u8>, Vec<Edit>) = Deserialize::deserialize(deserializer)?;
+    let mut rest = &ticks[..];
+    let mut previous: usize = 0;
+    let mut result = Vec::with_capacity(edits.len());
+    for edit in edits {
+        let delta = read_varint(&mut rest).ok_or_else(|| serde::de::Error::custom("invalid tick varint"))?;
+        previous = previous.wrapping_add(delta as usize);
+        result.push((previous, edit));
+    }
+    if !rest.is_empty() {
+        return Err(serde::de::Error::custom("more edit ticks than edits"));
+    }
+    return Ok(result);
+}
+
+/// Write an unsigned LEB128 varint, 7 bits per byte
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// Read an unsigned LEB128 varint, advancing the slice past it
+fn read_varint(bytes: &mut &[u8]) -> Option<u64> {
+    let mut value: u64 = 0;
+    for (i, byte) in bytes.iter().enumerate() {
+        if i >= 10 {
+            return None;
+        }
+        value |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            *bytes = &bytes[i + 1..];
+            return Some(value);
+        }
+    }
+    return None;
+}
+
 impl SimStateHistory {
     pub fn new() -> SimStateHistory {
         return SimStateHistory {
-            alpha_history: AttribHistory::new(),
-            beta_history: AttribHistory::new(),
-            gamma_history: AttribHistory::new(),
-            alpha_rand_history: AttribHistory::new(),
+            model: String::new(),
+            param_histories: Vec::new(),
             seed: 0,
             size: (0,0),
-            start_filled: Vec::new(),
+            start_filled: CellMask::new(0, 0),
+            edits: Vec::new(),
+            state: HistoryState::Idle,
+            keyframes: Keyframes::default(),
         };
     }
 
+    pub fn state(&self) -> HistoryState {
+        return self.state;
+    }
+
     // Track/record
 
-    /// Initiate the tracking of a simulation
-    pub fn init_tracking(&mut self, sim : &sim::SnowflakeSim) {
-        self.size = (sim.width, sim.height);
-        self.seed = sim.seed;
-        // if beta is greater than 1.0, all cells are frozen, do not record
-        if sim.background_vapor < 1.0 {
-            // Record the starting frozen cells
-            for y in 0..sim.height {
-                for x in 0..sim.width {
-                    if sim.get_water(x, y) >= 1.0 {
-                        self.start_filled.push((x,y));
+    /// Initiate the tracking of a simulation, recording its current
+    /// parameters, frozen cells and settings as the starting state
+    pub fn init_tracking(&mut self, sim : &dyn GrowthModel) {
+        self.state = HistoryState::Tracking;
+        self.edits.clear();
+        self.model = sim.model_name().to_string();
+        self.param_histories = sim.param_names().iter()
+            .map(|name| (name.to_string(), AttribHistory::new()))
+            .collect();
+        self.size = (sim.width(), sim.height());
+        self.seed = sim.seed();
+        // Record the starting frozen cells
+        self.start_filled = CellMask::from_fn(sim.width(), sim.height(), |x, y| sim.is_frozen(x, y));
+        // if all cells are frozen (high background vapor), do not record
+        if self.start_filled.cells().len() == sim.width() * sim.height() {
+            self.start_filled = CellMask::new(sim.width(), sim.height());
+        }
+        self.track_tick(sim);
+        let tick = sim.iteration_count();
+        let reiter_sim = sim.as_any().downcast_ref::<sim::SnowflakeSim>();
+        if let Some(reiter_sim) = reiter_sim {
+            self.edits.push((tick, Edit::RandomMode(reiter_sim.random_mode())));
+            if reiter_sim.boundary() != sim::Boundary::Fixed {
+                self.edits.push((tick, Edit::Boundary(reiter_sim.boundary())));
+            }
+            for param in ["alpha", "beta", "gamma"] {
+                if let Some(field) = reiter_sim.noise_field(param) {
+                    self.edits.push((tick, Edit::Noise(param.to_string(), Some(field))));
+                }
+            }
+            for (param, schedule) in reiter_sim.schedules() {
+                self.edits.push((tick, Edit::Schedule(param.clone(), Some(schedule.clone()))));
+            }
+        }
+        // Record the water levels which playback would not reproduce
+        // from the parameters, frozen cells and settings alone
+        if let Ok(mut start) = self.start_sim() {
+            self.apply_edits(start.as_mut(), 0);
+            for y in 0..sim.height() {
+                for x in 0..sim.width() {
+                    let water = sim.start_water(x, y);
+                    if water != start.start_water(x, y) {
+                        self.edits.push((tick, Edit::Water(x, y, water)));
                     }
                 }
             }
         }
-        self.track_tick(sim);
+        // Symmetric mode takes the state of the wedge, so it goes last
+        if let Some(center) = reiter_sim.and_then(|reiter_sim| reiter_sim.symmetry_center()) {
+            self.edits.push((tick, Edit::Symmetry(Some(center))));
+        }
+    }
+
+    /// Record an edit made before stepping iteration `tick`.
+    /// Edits are only recorded while tracking.
+    pub fn record(&mut self, tick: usize, edit: Edit) {
+        if self.state == HistoryState::Tracking {
+            self.edits.push((tick, edit));
+        }
     }
 
-    /// Track a simulation tick
-    pub fn track_tick(&mut self, sim : &sim::SnowflakeSim) {
-        let i = sim.iteration_count;
-        self.alpha_history.add(i, sim.vapor_diffusion);
-        self.beta_history.add(i, sim.background_vapor);
-        self.gamma_history.add(i, sim.vapor_addition);
-        self.alpha_rand_history.add(i, sim.vapor_diffusion_rand);
+    /// Track the parameters of a simulation which was changed directly,
+    /// rather than through recorded edits. Parameter changes are
+    /// applied after stepping to the current iteration count.
+    pub fn track_tick(&mut self, sim : &dyn GrowthModel) {
+        let i = sim.iteration_count();
+        for (name, history) in &mut self.param_histories {
+            if let Some(value) = sim.get_param(name) {
+                history.add(i, value);
+            }
+        }
     }
 
     // Playback
 
     /// Initiate the playback of a simulation 
-    pub fn init_playback(&self) -> sim::SnowflakeSim {
-        let alpha = self.alpha_history.get(0);
-        let beta = self.beta_history.get(0);
-        let gamma = self.gamma_history.get(0);
-        let mut sim = sim::SnowflakeSim::new(self.size.0, self.size.1, alpha, beta, gamma);
-        sim.vapor_diffusion_rand = self.alpha_rand_history.get(0);
+    pub fn init_playback(&mut self) -> Result<Box<dyn GrowthModel>, HistoryError> {
+        self.validate()?;
+        self.state = HistoryState::Playback;
+        let mut sim = self.start_sim()?;
+        self.apply_edits(sim.as_mut(), 0);
+        self.keyframes.clear();
+        self.keyframes.capture(sim.as_ref());
+        return Ok(sim);
+    }
+
+    /// Create the simulation at the start of the history, before the
+    /// edits made before the first iteration
+    pub fn start_sim(&self) -> Result<Box<dyn GrowthModel>, HistoryError> {
+        let mut sim = create_model(&self.model, self.size.0, self.size.1)
+            .ok_or_else(|| HistoryError::UnknownModel(self.model.clone()))?;
+        for (name, history) in &self.param_histories {
+            sim.set_param(name, history.get(0));
+        }
         sim.set_random_seed(self.seed);
-        for (x, y) in &self.start_filled {
-            sim.set_water(*x, *y, 1.0);
+        for (x, y) in self.start_filled.cells() {
+            sim.set_water(x, y, 1.0);
+        }
+        return Ok(sim);
+    }
+
+    /// Make sure playback of the history can not panic
+    fn validate(&self) -> Result<(), HistoryError> {
+        let (width, height) = self.size;
+        if width == 0 || height == 0 || width.saturating_mul(height) > MAX_GRID_CELLS {
+            return Err(HistoryError::InvalidData(format!("grid size {}x{}", width, height)));
+        }
+        if let Some((name, _)) = self.param_histories.iter().find(|(_, history)| history.history.is_empty()) {
+            return Err(HistoryError::InvalidData(format!("parameter {} has no values", name)));
+        }
+        if (self.start_filled.width(), self.start_filled.height()) != self.size || !self.start_filled.is_valid() {
+            return Err(HistoryError::InvalidData("starting cells do not fit the grid".to_string()));
         }
-        return sim;
+        // Edits are looked up by tick, which needs them in order
+        if self.edits.windows(2).any(|pair| pair[0].0 > pair[1].0) {
+            return Err(HistoryError::InvalidData("edits are not in the order of their iterations".to_string()));
+        }
+        for (_, edit) in &self.edits {
+            match edit {
+                Edit::Crystal(SeedShape::Mask { mask, .. })
+                    if !mask.is_valid() || mask.width().saturating_mul(mask.height()) > MAX_GRID_CELLS => {
+                    return Err(HistoryError::InvalidData("invalid seed crystal mask".to_string()));
+                }
+                Edit::Noise(param, Some(field)) if !field.is_valid() => {
+                    return Err(HistoryError::InvalidData(format!("invalid noise field of {}", param)));
+                }
+                Edit::Boundary(boundary) if !boundary.fits_height(height) => {
+                    return Err(HistoryError::InvalidData(format!("{:?} boundary on a grid of height {}", boundary, height)));
+                }
+                _ => {}
+            }
+        }
+        let cells = self.edits.iter().filter_map(|(_, edit)| {
+            return match edit {
+                Edit::Water(x, y, _) => Some((*x, *y)),
+                Edit::Symmetry(center) => *center,
+                _ => None,
+            };
+        });
+        for (x, y) in cells {
+            if x >= width || y >= height {
+                return Err(HistoryError::InvalidData(format!("cell {},{} is outside of the grid", x, y)));
+            }
+        }
+        return Ok(());
     }
 
     /// Playback a simulation tick
-    pub fn playback_tick(&self, sim: &mut sim::SnowflakeSim) {
-        let count = sim.iteration_count;
-        sim.vapor_diffusion = self.alpha_history.get(count) as f64;
-        sim.background_vapor = self.beta_history.get(count) as f64;
-        sim.vapor_addition = self.gamma_history.get(count) as f64;
-        sim.vapor_diffusion_rand = self.alpha_rand_history.get(count) as f64;
+    pub fn playback_tick(&mut self, sim: &mut dyn GrowthModel) {
+        self.apply_tick(sim);
+        self.keyframes.capture(sim);
+    }
+
+    /// Apply the parameter changes and edits recorded at the
+    /// current iteration count of the simulation
+    pub fn apply_tick(&self, sim: &mut dyn GrowthModel) {
+        let count = sim.iteration_count();
+        for (name, history) in &self.param_histories {
+            if let Some(value) = history.get_change(count) {
+                sim.set_param(name, value);
+            }
+        }
+        self.apply_edits(sim, count);
+    }
+
+    /// Discard every change recorded after iteration `tick`, so that the
+    /// history continues from that iteration when the simulation is rewound
+    pub fn truncate(&mut self, tick: usize) {
+        let end = self.edits.partition_point(|(t, _)| *t <= tick);
+        self.edits.truncate(end);
+        for (_, history) in &mut self.param_histories {
+            history.truncate(tick);
+        }
+    }
+
+    /// Seek the playback to an iteration. The latest keyframe before the
+    /// iteration is restored if seeking backwards, or if it is ahead of
+    /// the simulation, and the simulation is then stepped forward.
+    pub fn seek(&mut self, sim: &mut dyn GrowthModel, iteration: usize) {
+        let current = sim.iteration_count();
+        if let Some(keyframe) = self.keyframes.latest(iteration) {
+            if iteration < current || keyframe > current {
+                self.keyframes.restore(sim, keyframe);
+            }
+        }
+        while sim.iteration_count() < iteration {
+            sim.step();
+            self.playback_tick(sim);
+        }
+    }
+
+    /// Set the amount of iterations between playback keyframes
+    pub fn set_keyframe_interval(&mut self, interval: usize) {
+        self.keyframes.set_interval(interval);
+    }
+
+    /// Apply the edits made at a certain iteration count, in order
+    fn apply_edits(&self, sim: &mut dyn GrowthModel, tick: usize) {
+        // Edits are sorted by tick
+        let start = self.edits.partition_point(|(t, _)| *t < tick);
+        for (_, edit) in self.edits[start..].iter().take_while(|(t, _)| *t == tick) {
+            match edit {
+                Edit::Param(name, value) => { sim.set_param(name, *value); }
+                Edit::Seed(seed) => sim.set_random_seed(*seed),
+                Edit::Water(x, y, water) => sim.set_water(*x, *y, *water),
+                Edit::Symmetry(center) => {
+                    if let Some(reiter_sim) = sim.as_any_mut().downcast_mut::<sim::SnowflakeSim>() {
+                        match center {
+                            Some((cx, cy)) => reiter_sim.enable_symmetry(*cx, *cy),
+                            None => reiter_sim.disable_symmetry(),
+                        }
+                    }
+                }
+                Edit::RandomMode(mode) => {
+                    if let Some(reiter_sim) = sim.as_any_mut().downcast_mut::<sim::SnowflakeSim>() {
+                        reiter_sim.set_random_mode(*mode);
+                    }
+                }
+                Edit::Noise(param, field) => {
+                    if let Some(reiter_sim) = sim.as_any_mut().downcast_mut::<sim::SnowflakeSim>() {
+                        reiter_sim.set_noise_field(param, *field);
+                    }
+                }
+                Edit::Schedule(param, schedule) => {
+                    if let Some(reiter_sim) = sim.as_any_mut().downcast_mut::<sim::SnowflakeSim>() {
+                        reiter_sim.set_schedule(param, schedule.clone());
+                    }
+                }
+                Edit::Boundary(boundary) => {
+                    if let Some(reiter_sim) = sim.as_any_mut().downcast_mut::<sim::SnowflakeSim>() {
+                        reiter_sim.set_boundary(*boundary);
+                    }
+                }
+                Edit::Crystal(shape) => {
+                    for (x, y) in shape.cells(sim.width(), sim.height()) {
+                        sim.set_water(x, y, 1.0);
+                    }
+                }
+            }
+        }
     }
 
     /// Serialize the sim state history to a base64 string,
-    /// useful in URLs
+    /// useful in URLs. The string starts with a header of magic
+    /// bytes and the format version.
     pub fn serialize_to_str(&self) -> String {
-        // Serialize
+        // Serialize, this can only fail for unsupported serde types
         let serialized_bytes = bincode::serialize(&self).unwrap();
-        // Compress using flate2
+        // Compress using flate2, writing to a Vec can not fail
         let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::best());
         encoder.write_all(&serialized_bytes).unwrap();
         let compressed_bytes = encoder.finish().unwrap();
         // Encode as base64 string
-        return base64::encode_config(compressed_bytes, base64::URL_SAFE_NO_PAD);
+        let mut bytes = FORMAT_MAGIC.to_vec();
+        bytes.push(FORMAT_VERSION);
+        bytes.extend_from_slice(&compressed_bytes);
+        return base64::encode_config(bytes, base64::URL_SAFE_NO_PAD);
     }
 
-    /// Deserialize a base64 string into a sim state history
-    pub fn deserialize_from_str(string : String) -> SimStateHistory {
+    /// Deserialize a base64 string into a sim state history.
+    /// Strings from every earlier format version are supported.
+    pub fn deserialize_from_str(string : String) -> Result<SimStateHistory, HistoryError> {
         // Decode base64 string
-        let compressed_bytes = base64::decode_config(string, base64::URL_SAFE_NO_PAD).unwrap();
+        let bytes = base64::decode_config(string.trim(), base64::URL_SAFE_NO_PAD).map_err(HistoryError::Base64)?;
+        // Read the header, strings without one use the legacy format
+        let (version, compressed_bytes) = match bytes.strip_prefix(FORMAT_MAGIC) {
+            Some([version, rest @ ..]) => (*version, rest),
+            Some([]) => return Err(HistoryError::InvalidData("missing format version".to_string())),
+            None => (0, &bytes[..]),
+        };
         // Decompress
         let mut decoder = ZlibDecoder::new(Vec::<u8>::new());
-        decoder.write_all(&compressed_bytes).unwrap();
-        let serialized_bytes = decoder.finish().unwrap();
+        decoder.write_all(compressed_bytes).map_err(HistoryError::Decompress)?;
+        let serialized_bytes = decoder.finish().map_err(HistoryError::Decompress)?;
         // Deserialize
-        return bincode::deserialize(&serialized_bytes).unwrap();
+        let mut history = match version {
+            0 => {
+                let legacy: LegacyHistory = bincode::deserialize(&serialized_bytes).map_err(HistoryError::Decode)?;
+                SimStateHistory::from(legacy)
+            }
+            1 => {
+                let v1: HistoryV1 = bincode::deserialize(&serialized_bytes).map_err(HistoryError::Decode)?;
+                SimStateHistory::from(v1)
+            }
+            // Version 2 only differs by the random mode
+            2 | 3 => {
+                let v3: HistoryV3 = bincode::deserialize(&serialized_bytes).map_err(HistoryError::Decode)?;
+                SimStateHistory::from(v3)
+            }
+            4 => {
+                let v4: HistoryV4 = bincode::deserialize(&serialized_bytes).map_err(HistoryError::Decode)?;
+                SimStateHistory::from(v4)
+            }
+            FORMAT_VERSION => bincode::deserialize(&serialized_bytes).map_err(HistoryError::Decode)?,
+            _ => return Err(HistoryError::UnsupportedVersion(version)),
+        };
+        if version < 3 && history.model == sim::MODEL_NAME {
+            history.edits.insert(0, (0, Edit::RandomMode(sim::RandomMode::Buffer)));
+        }
+        return Ok(history);
+    }
+}
+
+impl From<LegacyHistory> for SimStateHistory {
+    fn from(legacy: LegacyHistory) -> SimStateHistory {
+        let mut history = SimStateHistory::new();
+        history.model = sim::MODEL_NAME.to_string();
+        history.param_histories = vec![
+            ("alpha".to_string(), legacy.alpha_history.into()),
+            ("beta".to_string(), legacy.beta_history.into()),
+            ("gamma".to_string(), legacy.gamma_history.into()),
+            ("alpha_rand".to_string(), legacy.alpha_rand_history.into()),
+        ];
+        history.seed = legacy.seed;
+        history.size = legacy.size;
+        history.start_filled = CellMask::from_cells(legacy.size.0, legacy.size.1, &legacy.start_filled);
+        return history;
+    }
+}
+
+impl From<HistoryV1> for SimStateHistory {
+    fn from(v1: HistoryV1) -> SimStateHistory {
+        let mut history = SimStateHistory::new();
+        history.model = v1.model;
+        history.param_histories = v1.param_histories.into_iter().map(|(name, attrib)| (name, attrib.into())).collect();
+        history.seed = v1.seed;
+        history.size = v1.size;
+        history.start_filled = CellMask::from_cells(v1.size.0, v1.size.1, &v1.start_filled);
+        history.edits = v1.edits;
+        return history;
+    }
+}
+
+impl From<HistoryV3> for SimStateHistory {
+    fn from(v3: HistoryV3) -> SimStateHistory {
+        let mut history = SimStateHistory::new();
+        history.model = v3.model;
+        history.param_histories = v3.param_histories;
+        history.seed = v3.seed;
+        history.size = v3.size;
+        history.start_filled = CellMask::from_cells(v3.size.0, v3.size.1, &v3.start_filled);
+        history.edits = v3.edits;
+        return history;
+    }
+}
+
+impl From<HistoryV4> for SimStateHistory {
+    fn from(v4: HistoryV4) -> SimStateHistory {
+        let mut history = SimStateHistory::new();
+        history.model = v4.model;
+        history.param_histories = v4.param_histories;
+        history.seed = v4.seed;
+        history.size = v4.size;
+        history.start_filled = v4.start_filled;
+        history.edits = v4.edits;
+        return history;
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::super::{gravner_sim, rewind, sim, SnowflakeSimContext};
+    use super::super::noise::NoiseKind;
+    use super::super::schedule::Interpolation;
+    use proptest::prelude::*;
 
     static GRID_WIDTH: usize = 100;
     static GRID_HEIGHT: usize = 100;
@@ -184,23 +733,394 @@ mod tests {
         }
     
         // Replay sim1 in sim2
-        let mut sim2 = tracker.init_playback();
+        let mut sim2 = tracker.init_playback().unwrap();
     
         for _ in 0..ITERATIONS {
             sim2.step();
-            tracker.playback_tick(&mut sim2);
+            tracker.playback_tick(sim2.as_mut());
         }
     
         // Make sure sim1 and sim2 are the same
         // This means that all the parameters were reproduced
         // correctly
-        assert!(compare_sims(&sim1, &sim2));
+        assert!(compare_sims(&sim1, sim2.as_ref()));
     }
-    
+
+    #[test]
+    fn test_history_tracking_gravner() {
+        let mut sim1 = gravner_sim::GravnerSim::new(GRID_WIDTH, GRID_HEIGHT);
+        sim1.set_water(GRID_WIDTH / 2 - 1, GRID_HEIGHT / 2 - 1, 1.0);
+        sim1.noise = 0.01;
+
+        let mut tracker = SimStateHistory::new();
+        tracker.init_tracking(&sim1);
+
+        for i in 0..ITERATIONS {
+            sim1.step();
+            if i == 20 {
+                sim1.attachment_threshold = 1.4;
+            }
+            tracker.track_tick(&sim1);
+        }
+
+        let serialized = tracker.serialize_to_str();
+        let mut tracker = SimStateHistory::deserialize_from_str(serialized).unwrap();
+        let mut sim2 = tracker.init_playback().unwrap();
+        assert_eq!(sim2.model_name(), gravner_sim::MODEL_NAME);
+
+        for _ in 0..ITERATIONS {
+            sim2.step();
+            tracker.playback_tick(sim2.as_mut());
+        }
+
+        assert!(compare_sims(&sim1, sim2.as_ref()));
+    }
+
+    #[test]
+    fn test_history_tracking_settings() {
+        // Settings made directly before tracking are part of the starting state
+        let mut sim1 = sim::SnowflakeSim::new(40, 40, 1.0, 0.4, 0.0001);
+        sim1.set_water(20, 20, 1.0);
+        sim1.set_water(5, 5, 0.9);
+        sim1.set_boundary(sim::Boundary::Circular);
+        let field = NoiseField { kind: NoiseKind::Value, amplitude: 0.2, scale: 8.0, octaves: 2, period: 0.0 };
+        sim1.set_noise_field("gamma", Some(field));
+        sim1.set_schedule("alpha", Some(Schedule::from_keyframes(Interpolation::Linear, &[(0, 1.0), (30, 2.0)])));
+        sim1.enable_symmetry(20, 20);
+        let mut tracker = SimStateHistory::new();
+        tracker.init_tracking(&sim1);
+
+        let mut gravner1 = gravner_sim::GravnerSim::new(40, 40);
+        gravner1.set_water(20, 20, 1.0);
+        gravner1.set_water(5, 5, 0.2);
+        let mut gravner_tracker = SimStateHistory::new();
+        gravner_tracker.init_tracking(&gravner1);
+
+        let mut sim2 = tracker.init_playback().unwrap();
+        let mut gravner2 = gravner_tracker.init_playback().unwrap();
+        for _ in 0..ITERATIONS {
+            sim1.step();
+            sim2.step();
+            tracker.playback_tick(sim2.as_mut());
+            gravner1.step();
+            gravner2.step();
+            gravner_tracker.playback_tick(gravner2.as_mut());
+        }
+        assert!(sim1.save_state() == sim2.save_state());
+        assert!(gravner1.save_state() == gravner2.save_state());
+    }
+
+    /// History strings written by earlier format versions, which must keep decoding
+    static LEGACY_HISTORY: &str = "eNpjYkAFH-y5GM6eAYE39oxw0VkzQeCmPRNcRNdZ5vUjMyl7EYY_Kz9e8k1KQFJtDAaX7S-xQPhyDKg0TCU_Gg0AB6MXCg";
+    static V1_HISTORY: &str = "U05GTAF42nVQMQ7CMAw0ECLo0oUJofICJiTWCDHzBkhFBEgUoaifgR_wDHZYysTKCgOsiAFSTJu67UlR7Ni585nDD1otQ6U5ZnW85WqzkBXI4iEYRr4Krepua3AR_89zGQRWuTfq3K6DtnBs7omW6xkVAGhaAiUtjq1S0tMYwuvQBfAw90idk9wwRCz7Vv0eF2OX7KFontRWP8Y5MZxb7OlocE8ajFQNY0akWgUksfX3_hmN_an4AL2YN-U";
+    static V3_HISTORY: &str = "U05GTAN42m1RMQ7CMAx0KxrRLmVgQAiVlYWVNSAegoKoKIgiVHXmE0zwA_7AwsgTGGFDLMydoNRp3DQnRfHJFztnMyiQhKs0TBgyB2-x2UXChQo-vIHRPExV8nTMcefy6VLEscoOp733c9TlHi08S8R2oVUHcEl1s8KjLcyS5gSyax8gQB7oeY3nlizC8_jRqmrs3_Ex9rWRmL6nPBazuZUqBztKm5Ya4BlFnbENdTCym797S36MQg57gDzbry_pIeJtoqlsuGj9KgU2ETDN6Rci_EKP";
+    static V4_HISTORY: &str = "U05GTAR42nVRvQrCMBC-Fhtslzo4iIiuLq6uUXwQiVisYkVKZ1_CSd_Ad3Bx9BEcdRMX505ae2muMf0g5D7y5X6-Y5AjDpZJEDNkDt5ivQ2FCyW8eQ2jWZCox-Mhw43LrwsRRep1MOm8HsM292jiaSw2cy07gEuymxUeLWGW1MeQXnoAXeRVd6HXeDaiRXgW3xtljf09Psa-ZpGpXTVz7tW1UDlYUY5tKUNPKGqNbPgHI7v6uWHJxiik-X3k6W51TvYhbxJNaeN56WchsImAaZN-AGiiQss";
+    /// Hash of the water of every cell after replaying `V3_HISTORY` or `V4_HISTORY`
+    static V3_WATER_HASH: u64 = 0x817ca090f2e4b1f;
+
+    #[test]
+    fn test_history_formats() {
+        // The legacy format changes alpha at tick 10 and gamma at tick 20
+        let mut tracker = SimStateHistory::deserialize_from_str(LEGACY_HISTORY.to_string()).unwrap();
+        let mut sim1 = tracker.init_playback().unwrap();
+        assert_eq!(sim1.model_name(), sim::MODEL_NAME);
+        assert_eq!(sim1.get_param("alpha_rand"), Some(0.3));
+        for _ in 0..30 {
+            sim1.step();
+            tracker.playback_tick(sim1.as_mut());
+        }
+        assert_eq!(sim1.get_param("alpha"), Some(0.9));
+        assert_eq!(sim1.get_param("gamma"), Some(0.002));
+        assert!(sim1.is_frozen(15, 15));
+
+        // Version 1 was recorded from these context edits
+        let mut ctx1 = SnowflakeSimContext::new(30, 30, 1.0, 0.4, 0.0001);
+        ctx1.set_random_mode("buffer");
+        ctx1.set_random_seed(1234);
+        ctx1.set_cell(15, 15, 1.0);
+        ctx1.set_alpha_rand(0.3);
+        (0..10).for_each(|_| ctx1.step_simulation());
+        ctx1.set_alpha(0.9);
+        ctx1.set_cell(3, 4, 1.0);
+        (0..10).for_each(|_| ctx1.step_simulation());
+        ctx1.set_gamma(0.002);
+        (0..10).for_each(|_| ctx1.step_simulation());
+        let mut ctx2 = SnowflakeSimContext::new(30, 30, 1.0, 0.4, 0.0001);
+        ctx2.init_playback(V1_HISTORY.to_string()).unwrap();
+        (0..30).for_each(|_| ctx2.step_simulation_playback());
+        assert!(compare_sims(ctx1.sim.as_ref(), ctx2.sim.as_ref()));
+
+        // Version 3 was recorded from these context edits, and must replay
+        // to the exact water recorded with it
+        let mut ctx1 = SnowflakeSimContext::new(30, 30, 1.0, 0.4, 0.0001);
+        ctx1.set_random_seed(4321);
+        ctx1.set_cell(15, 15, 1.0);
+        ctx1.set_alpha_rand(0.2);
+        ctx1.set_noise_field("beta", "value", 0.05, 6.0, 2, 0.0);
+        ctx1.set_schedule("gamma", "linear", vec![0, 40], vec![0.0001, 0.003]);
+        (0..20).for_each(|_| ctx1.step_simulation());
+        ctx1.set_alpha(0.8);
+        ctx1.set_cell(5, 6, 1.0);
+        (0..30).for_each(|_| ctx1.step_simulation());
+        // Version 4 was recorded from the same edits
+        for string in [V3_HISTORY, V4_HISTORY] {
+            let mut ctx2 = SnowflakeSimContext::new(30, 30, 1.0, 0.4, 0.0001);
+            ctx2.init_playback(string.to_string()).unwrap();
+            (0..50).for_each(|_| ctx2.step_simulation_playback());
+            assert!(compare_sims(ctx1.sim.as_ref(), ctx2.sim.as_ref()));
+            let mut hash: u64 = 0;
+            for y in 0..30 {
+                for x in 0..30 {
+                    hash = hash.wrapping_mul(31).wrapping_add(ctx2.sim.get_water(x, y).to_bits());
+                }
+            }
+            assert_eq!(hash, V3_WATER_HASH);
+            assert!(ctx2.sim.is_frozen(15, 15));
+        }
+    }
+
+    #[test]
+    fn test_playback_seek() {
+        let mut ctx1 = SnowflakeSimContext::new(40, 40, 1.0, 0.4, 0.0001);
+        ctx1.set_cell(20, 20, 1.0);
+        ctx1.set_beta_rand(0.2);
+        let mut states = Vec::new();
+        for i in 0..120 {
+            if i == 30 {
+                ctx1.set_gamma(0.01);
+                ctx1.set_random_seed(7);
+            }
+            states.push(ctx1.sim.save_state());
+            ctx1.step_simulation();
+        }
+
+        let mut ctx2 = SnowflakeSimContext::new(40, 40, 1.0, 0.4, 0.0001);
+        assert!(!ctx2.seek(10));
+        ctx2.init_playback(ctx1.get_simulation_string_repr()).unwrap();
+        ctx2.set_keyframe_interval(25);
+        // Forwards, backwards past a keyframe and forwards again
+        for iteration in [100, 37, 26, 110, 0] {
+            assert!(ctx2.seek(iteration));
+            assert_eq!(ctx2.get_iteration_count(), iteration);
+            assert!(ctx2.sim.save_state() == states[iteration]);
+        }
+    }
+
+    #[test]
+    fn test_long_attrib_history() {
+        let mut history = AttribHistory::new();
+        history.add(0, 1.0);
+        history.add(70000, 2.0);
+        history.add(4_000_000_000, 3.0);
+        let bytes = bincode::serialize(&history).unwrap();
+        // Length prefix, three values, and tick deltas of one, three and five bytes
+        assert_eq!(bytes.len(), 8 + 3 * 8 + 1 + 3 + 5);
+        let history: AttribHistory = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(history.get(69999), 1.0);
+        assert_eq!(history.get(70000), 2.0);
+        assert_eq!(history.get(3_999_999_999), 2.0);
+        assert_eq!(history.get_change(4_000_000_000), Some(3.0));
+    }
+
+    #[test]
+    fn test_long_edit_history() {
+        let mut history = SimStateHistory::new();
+        history.init_tracking(&sim::SnowflakeSim::new(20, 20, 1.0, 0.4, 0.0001));
+        for i in 0..1000 {
+            history.record(i * 1000, Edit::Water(i % 20, 3, 0.5));
+        }
+        // Tick deltas below 16,384 iterations take two bytes instead of
+        // eight, which saves more than the rest of the history takes
+        let bytes = bincode::serialize(&history).unwrap();
+        assert!(bytes.len() + 5 * 1000 <= bincode::serialize(&history.edits).unwrap().len());
+        let decoded: SimStateHistory = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(decoded.edits, history.edits);
+    }
+
+    #[test]
+    fn test_history_errors() {
+        let decode = |string: &str| SimStateHistory::deserialize_from_str(string.to_string()).err().unwrap();
+        let encode = |bytes: &[u8]| base64::encode_config(bytes, base64::URL_SAFE_NO_PAD);
+        assert!(matches!(decode("not base64!"), HistoryError::Base64(_)));
+        assert!(matches!(decode(&encode(b"not zlib")), HistoryError::Decompress(_)));
+        assert!(matches!(decode(&encode(b"SNFL\x63")), HistoryError::UnsupportedVersion(99)));
+        // A share URL cut off in the middle
+        let truncated = &V1_HISTORY[..V1_HISTORY.len() / 8 * 4];
+        assert!(matches!(decode(truncated), HistoryError::Decompress(_) | HistoryError::Decode(_)));
+
+        let mut encoder = ZlibEncoder::new(b"SNFL\x01".to_vec(), flate2::Compression::best());
+        encoder.write_all(&[1, 2, 3]).unwrap();
+        assert!(matches!(decode(&encode(&encoder.finish().unwrap())), HistoryError::Decode(_)));
+
+        // Rows of periodic grids with an odd height do not line up
+        let mut history = SimStateHistory::new();
+        history.init_tracking(&sim::SnowflakeSim::new(20, 21, 1.0, 0.4, 0.0001));
+        history.record(0, Edit::Boundary(sim::Boundary::Periodic));
+        let mut decoded = SimStateHistory::deserialize_from_str(history.serialize_to_str()).unwrap();
+        assert!(matches!(decoded.init_playback().err().unwrap(), HistoryError::InvalidData(_)));
+
+        // Noise fields which would take forever to sample
+        let mut history = SimStateHistory::new();
+        history.init_tracking(&sim::SnowflakeSim::new(20, 20, 1.0, 0.4, 0.0001));
+        let field = NoiseField { kind: NoiseKind::Perlin, amplitude: 0.5, scale: 10.0, octaves: u32::MAX, period: 0.0 };
+        history.record(0, Edit::Noise("beta".to_string(), Some(field)));
+        let mut decoded = SimStateHistory::deserialize_from_str(history.serialize_to_str()).unwrap();
+        assert!(matches!(decoded.init_playback().err().unwrap(), HistoryError::InvalidData(_)));
+
+        // Edits out of order, which playback would skip
+        let mut history = SimStateHistory::new();
+        history.init_tracking(&sim::SnowflakeSim::new(20, 20, 1.0, 0.4, 0.0001));
+        history.record(5, Edit::Water(3, 3, 1.0));
+        history.record(2, Edit::Water(4, 4, 1.0));
+        let mut decoded = SimStateHistory::deserialize_from_str(history.serialize_to_str()).unwrap();
+        assert!(matches!(decoded.init_playback().err().unwrap(), HistoryError::InvalidData(_)));
+
+        // A seed mask far larger than any grid, which would take forever to place
+        let mut bytes = Vec::new();
+        for value in [1u64 << 32, 1 << 32, 2] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        let mask: CellMask = bincode::deserialize(&bytes).unwrap();
+        assert!(mask.is_valid());
+        let mut history = SimStateHistory::new();
+        history.init_tracking(&sim::SnowflakeSim::new(20, 20, 1.0, 0.4, 0.0001));
+        history.record(0, Edit::Crystal(SeedShape::Mask { x: 10, y: 10, mask }));
+        let mut decoded = SimStateHistory::deserialize_from_str(history.serialize_to_str()).unwrap();
+        assert!(matches!(decoded.init_playback().err().unwrap(), HistoryError::InvalidData(_)));
+
+        // A failed playback leaves the context running
+        let mut ctx = SnowflakeSimContext::new(30, 30, 1.0, 0.4, 0.0001);
+        assert!(ctx.init_playback("broken".to_string()).is_err());
+        ctx.step_simulation();
+        assert_eq!(ctx.sim.iteration_count(), 1);
+    }
+
+    /// An edit or step made through the simulation context
+    #[derive(Clone, Debug)]
+    enum ContextOp {
+        Step(usize),
+        Param(usize, f64),
+        Seed(u64),
+        Water(usize, usize, f64),
+        Symmetry(Option<(usize, usize)>),
+        Rewind(usize),
+        RandomMode(bool),
+        Noise(usize, Option<(bool, f64, f64)>),
+        Schedule(usize, usize, Vec<(u32, f64)>),
+        Crystal(usize, usize, usize, usize),
+        Boundary(usize),
+    }
+
+    fn context_op() -> impl Strategy<Value = ContextOp> {
+        let size = PROPTEST_GRID_SIZE;
+        return prop_oneof![
+            (1..6usize).prop_map(ContextOp::Step),
+            (0..8usize, 0.0..1.0f64).prop_map(|(i, value)| ContextOp::Param(i, value)),
+            any::<u64>().prop_map(ContextOp::Seed),
+            (0..size, 0..size, 0.0..1.5f64).prop_map(|(x, y, water)| ContextOp::Water(x, y, water)),
+            proptest::option::of((0..size, 0..size)).prop_map(ContextOp::Symmetry),
+            (0..25usize).prop_map(ContextOp::Rewind),
+            any::<bool>().prop_map(ContextOp::RandomMode),
+            (0..3usize, proptest::option::of((any::<bool>(), 0.0..1.0f64, 1.0..20.0f64)))
+                .prop_map(|(param, field)| ContextOp::Noise(param, field)),
+            (0..6usize, 0..4usize, proptest::collection::vec((0..100u32, 0.0..1.0f64), 0..4))
+                .prop_map(|(param, interpolation, keyframes)| ContextOp::Schedule(param, interpolation, keyframes)),
+            (0..6usize, 0..size, 0..size, 0..5usize)
+                .prop_map(|(shape, x, y, radius)| ContextOp::Crystal(shape, x, y, radius)),
+            (0..5usize).prop_map(ContextOp::Boundary),
+        ];
+    }
+
+    static PROPTEST_GRID_SIZE: usize = 16;
+
+    /// Apply an edit to a context, as a user of the site would
+    fn apply_op(ctx: &mut SnowflakeSimContext, op: &ContextOp) {
+        match op {
+            ContextOp::Step(n) => (0..*n).for_each(|_| ctx.step_simulation()),
+            ContextOp::Param(i, value) => {
+                let names = ctx.sim.param_names();
+                ctx.set_param(names[i % names.len()], *value);
+            }
+            ContextOp::Seed(seed) => ctx.set_random_seed(*seed),
+            ContextOp::Water(x, y, water) => ctx.set_cell(*x, *y, *water),
+            ContextOp::Symmetry(Some((cx, cy))) => { ctx.enable_symmetry(*cx, *cy); }
+            ContextOp::Symmetry(None) => ctx.disable_symmetry(),
+            ContextOp::Rewind(n) => { ctx.rewind(*n); }
+            ContextOp::RandomMode(counter) => { ctx.set_random_mode(if *counter { "counter" } else { "buffer" }); }
+            ContextOp::Noise(param, field) => {
+                let param = ["alpha", "beta", "gamma"][*param];
+                match field {
+                    Some((perlin, amplitude, scale)) => {
+                        let kind = if *perlin { "perlin" } else { "value" };
+                        ctx.set_noise_field(param, kind, *amplitude, *scale, 2, 10.0);
+                    }
+                    None => { ctx.clear_noise_field(param); }
+                }
+            }
+            ContextOp::Schedule(param, interpolation, keyframes) => {
+                let param = ctx.sim.param_names()[*param % ctx.sim.param_names().len()];
+                match ["linear", "smoothstep", "cubic"].get(*interpolation) {
+                    Some(interpolation) => {
+                        let (iterations, values) = keyframes.iter().copied().unzip();
+                        ctx.set_schedule(param, interpolation, iterations, values);
+                    }
+                    None => { ctx.clear_schedule(param); }
+                }
+            }
+            ContextOp::Crystal(shape, x, y, radius) => match shape {
+                0 => ctx.seed_single(*x, *y),
+                1 => ctx.seed_hexagon(*x, *y, *radius),
+                2 => ctx.seed_line(*x, *y, *radius * 2 + 1, *radius),
+                3 => ctx.seed_ring(*x, *y, *radius),
+                4 => ctx.seed_scatter(*x, *y, *radius, *radius * 2, *x as u64),
+                _ => { ctx.seed_mask(*x, *y, 3, 2, vec![1, 0, 1, 0, 1, *radius as u8]); }
+            },
+            ContextOp::Boundary(boundary) => {
+                ctx.set_boundary(["fixed", "circular", "absorbing", "periodic", "reflecting"][*boundary]);
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_history_replays_context_edits(
+            model in prop_oneof![Just(sim::MODEL_NAME), Just(gravner_sim::MODEL_NAME)],
+            setup in proptest::collection::vec(context_op(), 0..10),
+            ops in proptest::collection::vec(context_op(), 0..30),
+        ) {
+            let size = PROPTEST_GRID_SIZE;
+            let mut ctx1 = SnowflakeSimContext::new_with_model(model, size, size).unwrap();
+            ctx1.set_rewind_buffer(100, rewind::DEFAULT_REWIND_INTERVAL);
+            ctx1.set_cell(size / 2, size / 2, 1.0);
+            // Edits before restarting the tracking become the starting state
+            for op in setup.iter().filter(|op| !matches!(op, ContextOp::Step(_) | ContextOp::Rewind(_))) {
+                apply_op(&mut ctx1, op);
+            }
+            ctx1.init_tracking();
+            for op in &ops {
+                apply_op(&mut ctx1, op);
+            }
+
+            // Replay in a context with different starting parameters
+            let mut ctx2 = SnowflakeSimContext::new(size, size, 0.5, 0.5, 0.5);
+            ctx2.init_playback(ctx1.get_simulation_string_repr()).unwrap();
+            while ctx2.sim.iteration_count() < ctx1.sim.iteration_count() {
+                ctx2.step_simulation_playback();
+            }
+            for y in 0..size {
+                for x in 0..size {
+                    prop_assert_eq!(ctx1.sim.get_water(x, y).to_bits(), ctx2.sim.get_water(x, y).to_bits());
+                }
+            }
+        }
+    }
+
     /// Compare two simulation states
-    fn compare_sims(sim1 : &sim::SnowflakeSim, sim2: &sim::SnowflakeSim) -> bool {
-        for y in 0..GRID_HEIGHT {
-            for x in 0..GRID_WIDTH {
+    fn compare_sims(sim1 : &dyn GrowthModel, sim2: &dyn GrowthModel) -> bool {
+        for y in 0..sim1.height() {
+            for x in 0..sim1.width() {
                 let water1 = sim1.get_water(x, y);
                 let water2 = sim2.get_water(x, y);
                 if (water1 - water2).abs() > f64::EPSILON {
@@ -210,4 +1130,4 @@ mod tests {
         }
         return true;
     }    
-}
\ No newline at end of file
+}
@@ -0,0 +1,137 @@
+use super::growth_model::{create_model, GrowthModel};
+use super::sim_history::MAX_GRID_CELLS;
+
+use serde::{Serialize, Deserialize};
+use flate2::write::ZlibEncoder;
+use flate2::write::ZlibDecoder;
+use std::fmt;
+use std::io::Write;
+
+/// Magic bytes at the start of a snapshot
+const SNAPSHOT_MAGIC: &[u8; 4] = b"SNFS";
+/// Version of the snapshot format, written after the magic bytes
+const SNAPSHOT_VERSION: u8 = 1;
+
+/// Errors from loading a simulation snapshot
+#[derive(Debug)]
+pub enum SnapshotError {
+    /// The data does not start with the snapshot magic bytes
+    NotASnapshot,
+    /// The snapshot was written by a newer format version
+    UnsupportedVersion(u8),
+    /// The data could not be decompressed
+    Decompress(std::io::Error),
+    /// The decompressed data does not match the format
+    Decode(bincode::Error),
+    /// The snapshot uses a growth model which does not exist
+    UnknownModel(String),
+    /// The snapshot decoded, but does not describe a valid simulation
+    InvalidData(String),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            SnapshotError::NotASnapshot => write!(f, "not a simulation snapshot"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "snapshot format version {} is not supported, the latest is {}", version, SNAPSHOT_VERSION)
+            }
+            SnapshotError::Decompress(err) => write!(f, "could not decompress snapshot: {}", err),
+            SnapshotError::Decode(err) => write!(f, "could not decode snapshot: {}", err),
+            SnapshotError::UnknownModel(model) => write!(f, "unknown growth model '{}' in snapshot", model),
+            SnapshotError::InvalidData(reason) => write!(f, "invalid snapshot: {}", reason),
+        };
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+/// The growth model and grid size needed to recreate a simulation,
+/// together with the state saved by the model
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    model: String,
+    width: usize,
+    height: usize,
+    state: Vec<u8>,
+}
+
+/// Save the complete state of a simulation, so it can be restored exactly
+/// without replaying it. The snapshot is compressed and starts with a
+/// header of magic bytes and the format version.
+pub fn save_snapshot(sim: &dyn GrowthModel) -> Vec<u8> {
+    let snapshot = Snapshot {
+        model: sim.model_name().to_string(),
+        width: sim.width(),
+        height: sim.height(),
+        state: sim.save_state(),
+    };
+    // Serialize, this can only fail for unsupported serde types
+    let bytes = bincode::serialize(&snapshot).unwrap();
+    let mut header = SNAPSHOT_MAGIC.to_vec();
+    header.push(SNAPSHOT_VERSION);
+    let mut encoder = ZlibEncoder::new(header, flate2::Compression::best());
+    encoder.write_all(&bytes).unwrap();
+    return encoder.finish().unwrap();
+}
+
+/// Load a simulation from a snapshot created by `save_snapshot`
+pub fn load_snapshot(data: &[u8]) -> Result<Box<dyn GrowthModel>, SnapshotError> {
+    let body = data.strip_prefix(SNAPSHOT_MAGIC).ok_or(SnapshotError::NotASnapshot)?;
+    let (version, body) = body.split_first().ok_or(SnapshotError::NotASnapshot)?;
+    if *version != SNAPSHOT_VERSION {
+        return Err(SnapshotError::UnsupportedVersion(*version));
+    }
+    let mut decoder = ZlibDecoder::new(Vec::new());
+    decoder.write_all(body).map_err(SnapshotError::Decompress)?;
+    let bytes = decoder.finish().map_err(SnapshotError::Decompress)?;
+    let snapshot: Snapshot = bincode::deserialize(&bytes).map_err(SnapshotError::Decode)?;
+
+    let (width, height) = (snapshot.width, snapshot.height);
+    if width == 0 || height == 0 || width.saturating_mul(height) > MAX_GRID_CELLS {
+        return Err(SnapshotError::InvalidData(format!("grid size {}x{}", width, height)));
+    }
+    let mut sim = create_model(&snapshot.model, width, height)
+        .ok_or_else(|| SnapshotError::UnknownModel(snapshot.model.clone()))?;
+    if !sim.load_state(&snapshot.state) {
+        return Err(SnapshotError::InvalidData(format!("state does not fit a {}x{} {} model", width, height, snapshot.model)));
+    }
+    return Ok(sim);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{gravner_sim, sim};
+
+    #[test]
+    fn test_snapshot_restore() {
+        for name in [sim::MODEL_NAME, gravner_sim::MODEL_NAME] {
+            let mut model = create_model(name, 40, 30).unwrap();
+            // Randomization makes sure the random state is restored too
+            model.set_param(if name == sim::MODEL_NAME { "alpha_rand" } else { "sigma" }, 0.3);
+            model.set_water(20, 15, 1.0);
+            for _ in 0..100 {
+                model.step();
+            }
+
+            let snapshot = save_snapshot(model.as_ref());
+            let mut loaded = load_snapshot(&snapshot).unwrap();
+            assert_eq!(loaded.model_name(), name);
+            assert_eq!((loaded.width(), loaded.height()), (40, 30));
+            assert_eq!(loaded.iteration_count(), 100);
+            // Both continue exactly the same
+            for _ in 0..50 {
+                model.step();
+                loaded.step();
+            }
+            assert!(loaded.save_state() == model.save_state());
+        }
+
+        let snapshot = save_snapshot(&sim::SnowflakeSim::new(10, 10, 1.0, 0.4, 0.0001));
+        assert!(matches!(load_snapshot(b"SNFL\x02"), Err(SnapshotError::NotASnapshot)));
+        assert!(matches!(load_snapshot(b"SNFS\x09"), Err(SnapshotError::UnsupportedVersion(9))));
+        let truncated = load_snapshot(&snapshot[..snapshot.len() / 2]);
+        assert!(matches!(truncated, Err(SnapshotError::Decompress(_) | SnapshotError::Decode(_))));
+    }
+}
@@ -0,0 +1,171 @@
+use super::growth_model::GrowthModel;
+use super::{corner_key, edge_neighbour, hex_corner, hex_pixel_coord, CornerKey, HEX_SIZE};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// A closed polygon, as a list of corner positions in the same
+/// coordinates as `hex_pixel_coord`. The last corner connects to the first.
+pub type Polygon = Vec<(f32, f32)>;
+
+/// Settings for exporting a simulation as an SVG image
+#[derive(Clone, Debug)]
+pub struct SvgOptions {
+    /// SVG user units per hexagon size
+    pub scale: f32,
+    /// Fill color of the frozen crystal
+    pub fill: String,
+    /// Water levels to additionally draw outlines of, such as
+    /// the display color cutoff of 0.6
+    pub contour_levels: Vec<f64>,
+    /// Stroke color of the contours
+    pub contour_stroke: String,
+    /// Stroke width of the contours, in hexagon sizes
+    pub contour_width: f32,
+}
+
+impl Default for SvgOptions {
+    fn default() -> SvgOptions {
+        return SvgOptions {
+            scale: 10.0,
+            fill: "#80d1f5".to_string(),
+            contour_levels: Vec::new(),
+            contour_stroke: "#2a6f8f".to_string(),
+            contour_width: 0.1,
+        };
+    }
+}
+
+/// Trace the outline of all cells with a water level of at least `level`
+/// into closed polygons. Outer boundaries wind counter-clockwise and
+/// holes wind clockwise, so both the even-odd and nonzero fill rules
+/// render holes correctly.
+pub fn trace_outline(sim: &dyn GrowthModel, level: f64) -> Vec<Polygon> {
+    let inside = |x: isize, y: isize| {
+        return x >= 0 && y >= 0 && (x as usize) < sim.width() && (y as usize) < sim.height()
+            && sim.get_water(x as usize, y as usize) >= level;
+    };
+
+    // Collect every cell edge which separates an inside cell from an
+    // outside cell. Edges follow the corner order of the inside cell.
+    let mut edges: Vec<(CornerKey, CornerKey)> = Vec::new();
+    let mut positions: HashMap<CornerKey, (f32, f32)> = HashMap::new();
+    for y in 0..sim.height() {
+        for x in 0..sim.width() {
+            if !inside(x as isize, y as isize) {
+                continue;
+            }
+            let (cx, cy) = hex_pixel_coord(x, y, HEX_SIZE);
+            for i in 0..6 {
+                let (nx, ny) = edge_neighbour(x as isize, y as isize, i);
+                if inside(nx, ny) {
+                    continue;
+                }
+                let from = hex_corner(cx, cy, HEX_SIZE, i);
+                let to = hex_corner(cx, cy, HEX_SIZE, (i + 1) % 6);
+                positions.entry(corner_key(from)).or_insert(from);
+                positions.entry(corner_key(to)).or_insert(to);
+                edges.push((corner_key(from), corner_key(to)));
+            }
+        }
+    }
+
+    // Only three cells meet in a corner, so every boundary corner
+    // has exactly one outgoing boundary edge
+    let mut next: HashMap<CornerKey, CornerKey> = edges.iter().copied().collect();
+    let mut polygons = Vec::new();
+    for (start, _) in edges {
+        if !next.contains_key(&start) {
+            continue;
+        }
+        let mut polygon = Vec::new();
+        let mut corner = start;
+        while let Some(to) = next.remove(&corner) {
+            polygon.push(positions[&corner]);
+            corner = to;
+        }
+        polygons.push(polygon);
+    }
+    return polygons;
+}
+
+/// Export the frozen crystal of a simulation as an SVG image,
+/// with optional contours at other water levels
+pub fn render_svg(sim: &dyn GrowthModel, options: &SvgOptions) -> String {
+    // The bounds of the whole grid, to keep the crystal in place
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (0.0f32, 0.0f32, 0.0f32, 0.0f32);
+    for y in 0..sim.height() {
+        for x in [0, sim.width().saturating_sub(1)] {
+            let (px, py) = hex_pixel_coord(x, y, HEX_SIZE);
+            for i in 0..6 {
+                let (cx, cy) = hex_corner(px, py, HEX_SIZE, i);
+                min_x = min_x.min(cx);
+                min_y = min_y.min(cy);
+                max_x = max_x.max(cx);
+                max_y = max_y.max(cy);
+            }
+        }
+    }
+    let width = (max_x - min_x) * options.scale;
+    let height = (max_y - min_y) * options.scale;
+    // The display has y pointing up, SVG has y pointing down
+    let to_svg = |(px, py): (f32, f32)| {
+        return ((px - min_x) * options.scale, (max_y - py) * options.scale);
+    };
+
+    let mut svg = String::new();
+    // Writing to a String can not fail
+    writeln!(svg, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w:.2}\" height=\"{h:.2}\" viewBox=\"0 0 {w:.2} {h:.2}\">",
+        w = width, h = height).unwrap();
+    let crystal = path_data(&trace_outline(sim, 1.0), &to_svg);
+    writeln!(svg, "  <path d=\"{}\" fill=\"{}\" fill-rule=\"evenodd\"/>", crystal, options.fill).unwrap();
+    for level in &options.contour_levels {
+        let contour = path_data(&trace_outline(sim, *level), &to_svg);
+        writeln!(svg, "  <path d=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{:.2}\" data-level=\"{}\"/>",
+            contour, options.contour_stroke, options.contour_width * options.scale, level).unwrap();
+    }
+    svg.push_str("</svg>\n");
+    return svg;
+}
+
+/// Create SVG path data from a list of polygons
+fn path_data(polygons: &[Polygon], to_svg: &dyn Fn((f32, f32)) -> (f32, f32)) -> String {
+    let mut data = String::new();
+    for polygon in polygons {
+        for (i, corner) in polygon.iter().enumerate() {
+            let (x, y) = to_svg(*corner);
+            write!(data, "{}{:.3} {:.3} ", if i == 0 { "M" } else { "L" }, x, y).unwrap();
+        }
+        data.push('Z');
+    }
+    return data;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::sim;
+    use super::super::sim::get_neighbours;
+
+    #[test]
+    fn test_trace_outline() {
+        let mut simulation = sim::SnowflakeSim::new(20, 20, 1.0, 0.4, 0.0001);
+        simulation.set_water(10, 10, 1.0);
+        let polygons = trace_outline(&simulation, 1.0);
+        assert_eq!(polygons.len(), 1);
+        assert_eq!(polygons[0].len(), 6);
+
+        // A ring of cells around the center leaves a hole
+        let mut simulation = sim::SnowflakeSim::new(20, 20, 1.0, 0.4, 0.0001);
+        for (x, y) in get_neighbours(11, 11) {
+            simulation.set_water(x as usize - 1, y as usize - 1, 1.0);
+        }
+        let mut lengths: Vec<usize> = trace_outline(&simulation, 1.0).iter().map(|p| p.len()).collect();
+        lengths.sort();
+        assert_eq!(lengths, vec![6, 18]);
+
+        let options = SvgOptions { contour_levels: vec![0.6], ..SvgOptions::default() };
+        let svg = render_svg(&simulation, &options);
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(svg.matches("<path").count(), 2);
+    }
+}
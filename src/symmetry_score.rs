@@ -0,0 +1,116 @@
+use super::growth_model::GrowthModel;
+use super::hex_symmetry::{cube_to_offset, offset_to_cube, transform};
+
+use wasm_bindgen::prelude::*;
+
+/// Amount of hexagonal symmetry operations, see `hex_symmetry::transform`
+pub const SYMMETRY_OPERATIONS: usize = 12;
+
+/// How closely the crystal and the water field match their images under
+/// the 12 hexagonal symmetry operations around the seed. Operation 0 is
+/// the identity, see `hex_symmetry::transform` for the others.
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SymmetryScore {
+    /// Intersection over union of the frozen cells and their image,
+    /// for every operation
+    #[wasm_bindgen(skip)]
+    pub frozen_iou: Vec<f64>,
+    /// Root mean square difference of the water levels and their image,
+    /// for every operation
+    #[wasm_bindgen(skip)]
+    pub water_l2: Vec<f64>,
+    /// Mean intersection over union of the operations other than the
+    /// identity, 1 for a perfectly symmetric crystal
+    pub index: f64,
+}
+
+#[wasm_bindgen]
+impl SymmetryScore {
+    /// Get the intersection over union of the frozen cells per operation
+    pub fn get_frozen_iou(&self) -> Vec<f64> {
+        return self.frozen_iou.clone();
+    }
+
+    /// Get the root mean square water level difference per operation
+    pub fn get_water_l2(&self) -> Vec<f64> {
+        return self.water_l2.clone();
+    }
+}
+
+/// Compare a simulation with its images under the hexagonal symmetry
+/// operations. Cells whose image lies outside of the grid are skipped.
+///
+/// * `seed` - grid position the operations are centered on
+pub fn measure(sim: &dyn GrowthModel, seed: (usize, usize)) -> SymmetryScore {
+    let (width, height) = (sim.width() as isize, sim.height() as isize);
+    // The hexagonal layout is defined in padded coordinates, where
+    // even rows are shifted instead of odd rows
+    let center = offset_to_cube(seed.0 as isize + 1, seed.1 as isize + 1);
+    let image = |x: isize, y: isize, op: usize| {
+        let cube = offset_to_cube(x + 1, y + 1);
+        let cube = transform((cube.0 - center.0, cube.1 - center.1, cube.2 - center.2), op);
+        let (x, y) = cube_to_offset((cube.0 + center.0, cube.1 + center.1, cube.2 + center.2));
+        return (x - 1, y - 1);
+    };
+
+    let mut score = SymmetryScore::default();
+    for op in 0..SYMMETRY_OPERATIONS {
+        let (mut intersection, mut union) = (0, 0);
+        let (mut squared_error, mut compared) = (0.0, 0);
+        for y in 0..height {
+            for x in 0..width {
+                let (ix, iy) = image(x, y, op);
+                if ix < 0 || iy < 0 || ix >= width || iy >= height {
+                    continue;
+                }
+                let (a, b) = ((x as usize, y as usize), (ix as usize, iy as usize));
+                let (frozen_a, frozen_b) = (sim.is_frozen(a.0, a.1), sim.is_frozen(b.0, b.1));
+                intersection += (frozen_a && frozen_b) as usize;
+                union += (frozen_a || frozen_b) as usize;
+                squared_error += (sim.get_water(a.0, a.1) - sim.get_water(b.0, b.1)).powi(2);
+                compared += 1;
+            }
+        }
+        score.frozen_iou.push(if union == 0 { 1.0 } else { intersection as f64 / union as f64 });
+        score.water_l2.push(if compared == 0 { 0.0 } else { (squared_error / compared as f64).sqrt() });
+    }
+    score.index = score.frozen_iou[1..].iter().sum::<f64>() / (SYMMETRY_OPERATIONS - 1) as f64;
+    return score;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::sim::SnowflakeSim;
+
+    #[test]
+    fn test_symmetry_score() {
+        // Seeds on even and odd rows, the row offset must not break symmetry
+        for seed in [(30, 30), (31, 31)] {
+            let mut sim = SnowflakeSim::new(60, 60, 1.0, 0.4, 0.0001);
+            sim.set_water(seed.0, seed.1, 1.0);
+            for _ in 0..150 {
+                sim.step();
+            }
+            let score = measure(&sim, seed);
+            assert_eq!(score.frozen_iou, vec![1.0; SYMMETRY_OPERATIONS]);
+            // The square edge of the grid differs slightly from its images
+            assert!(score.water_l2.iter().all(|l2| *l2 < 1e-4));
+            assert_eq!(score.index, 1.0);
+        }
+
+        // Random parameters break the symmetry
+        let mut sim = SnowflakeSim::new(60, 60, 1.0, 0.4, 0.0001);
+        sim.set_param("alpha_rand", 0.5);
+        sim.set_water(30, 30, 1.0);
+        for _ in 0..150 {
+            sim.step();
+        }
+        let score = measure(&sim, (30, 30));
+        assert_eq!(score.frozen_iou[0], 1.0);
+        assert_eq!(score.water_l2[0], 0.0);
+        assert!(score.index < 1.0);
+        assert!(score.water_l2[1..].iter().all(|l2| *l2 > 1e-3));
+    }
+}
@@ -0,0 +1,311 @@
+use super::growth_model::GrowthModel;
+use super::morphology::cell_center;
+use serde::{Deserialize, Serialize};
+
+/// Iterations over which the change of the mass of the crystal is measured,
+/// see `StopConditions::mass_tolerance`. Long enough for the cells next to
+/// the crystal to freeze, as the mass only changes when cells freeze
+/// without vapor addition.
+pub const MASS_WINDOW: usize = 100;
+
+/// Conditions under which a simulation has finished growing.
+/// Conditions which are `None` are not checked.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct StopConditions {
+    /// Stop when the crystal is within this amount of steps of the edge
+    /// of the simulated area, 1 for a crystal touching it
+    pub edge_distance: Option<usize>,
+    /// Stop when no cell has frozen for this amount of iterations
+    pub stall_iterations: Option<usize>,
+    /// Stop when the mass of the crystal, the water of the frozen cells,
+    /// changed by less than this fraction over the last `MASS_WINDOW` iterations
+    pub mass_tolerance: Option<f64>,
+    /// Stop when a frozen cell is this far from the seed, in cells
+    pub max_radius: Option<f64>,
+}
+
+impl StopConditions {
+    /// Is any condition checked?
+    pub fn is_enabled(&self) -> bool {
+        return self.edge_distance.is_some()
+            || self.stall_iterations.is_some()
+            || self.mass_tolerance.is_some()
+            || self.max_radius.is_some();
+    }
+
+    /// Set a condition by its name, edge, stall, mass or radius.
+    /// Limits of edge and stall are rounded down to whole steps.
+    /// Returns false for unknown names.
+    pub fn set_by_name(&mut self, name: &str, limit: f64) -> bool {
+        match name {
+            "edge" => self.edge_distance = Some(limit as usize),
+            "stall" => self.stall_iterations = Some(limit as usize),
+            "mass" => self.mass_tolerance = Some(limit),
+            "radius" => self.max_radius = Some(limit),
+            _ => return false,
+        }
+        return true;
+    }
+}
+
+/// Whether a simulation is still growing, or the stop condition it met
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GrowthStatus {
+    Growing,
+    /// The crystal reached the edge
+    Edge,
+    /// No cell has frozen for a while
+    Stalled,
+    /// The mass of the crystal stopped changing
+    Converged,
+    /// The crystal reached the maximum radius
+    Radius,
+}
+
+impl GrowthStatus {
+    /// Get the name of the status, growing, edge, stalled, converged or radius
+    pub fn name(&self) -> &'static str {
+        return match self {
+            GrowthStatus::Growing => "growing",
+            GrowthStatus::Edge => "edge",
+            GrowthStatus::Stalled => "stalled",
+            GrowthStatus::Converged => "converged",
+            GrowthStatus::Radius => "radius",
+        };
+    }
+
+    /// Has a stop condition been met?
+    pub fn is_stopped(&self) -> bool {
+        return *self != GrowthStatus::Growing;
+    }
+}
+
+/// Changes of the frozen cells in an iteration, see `GrowthTracker::update`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GrowthChanges {
+    /// Change of the water of the frozen cells
+    pub mass: f64,
+    /// Grid positions of the cells which froze
+    pub frozen: Vec<(usize, usize)>,
+    /// Did a frozen cell thaw? The whole simulation is measured again then.
+    pub thawed: bool,
+}
+
+/// Measurements of the crystal over the iterations,
+/// which the stop conditions are checked against
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct GrowthTracker {
+    /// Center of the frozen cells when they were first measured,
+    /// which radii are measured from
+    origin: Option<(f64, f64)>,
+    frozen: usize,
+    /// Iteration a cell last froze at
+    last_growth: usize,
+    /// Water of the frozen cells
+    mass: f64,
+    /// Iteration and mass at the start of the current window
+    window_start: (usize, f64),
+    /// Relative change of the mass over the last complete window
+    mass_change: Option<f64>,
+    edge_distance: Option<usize>,
+    radius: f64,
+    /// Was the simulation changed other than by stepping it since it was measured?
+    stale: bool,
+}
+
+impl GrowthTracker {
+    /// Start tracking a simulation, stalls are counted from its current iteration
+    ///
+    /// * `edge_distance` - steps from a grid position to the edge
+    pub fn new(sim: &dyn GrowthModel, edge_distance: &dyn Fn(usize, usize) -> usize) -> GrowthTracker {
+        let mut tracker = GrowthTracker { last_growth: sim.iteration_count(), ..GrowthTracker::default() };
+        tracker.measure(sim, edge_distance);
+        tracker.window_start = (sim.iteration_count(), tracker.mass);
+        return tracker;
+    }
+
+    /// Measure the whole simulation at the next update, as it was
+    /// changed other than by stepping it
+    pub fn invalidate(&mut self) {
+        self.stale = true;
+    }
+
+    /// Update the measurements after an iteration from the cells which
+    /// changed in it. The whole simulation is only measured if it was
+    /// invalidated or a cell thawed.
+    ///
+    /// * `edge_distance` - steps from a grid position to the edge
+    pub fn update(&mut self, sim: &dyn GrowthModel, changes: &GrowthChanges, edge_distance: &dyn Fn(usize, usize) -> usize) {
+        let iteration = sim.iteration_count();
+        let frozen = self.frozen;
+        if self.stale || changes.thawed {
+            self.measure(sim, edge_distance);
+        } else {
+            self.mass += changes.mass;
+            self.add_frozen(&changes.frozen, edge_distance);
+        }
+        if self.frozen != frozen {
+            self.last_growth = iteration;
+        }
+        if iteration >= self.window_start.0 + MASS_WINDOW {
+            let start = self.window_start.1;
+            self.mass_change = Some(if self.mass == 0.0 { 0.0 } else { ((self.mass - start) / self.mass).abs() });
+            self.window_start = (iteration, self.mass);
+        }
+    }
+
+    /// Get the first stop condition which has been met
+    ///
+    /// * `iteration` - the current iteration of the simulation
+    pub fn status(&self, conditions: &StopConditions, iteration: usize) -> GrowthStatus {
+        if conditions.edge_distance.is_some_and(|limit| self.edge_distance.is_some_and(|distance| distance <= limit)) {
+            return GrowthStatus::Edge;
+        }
+        if conditions.max_radius.is_some_and(|limit| self.radius >= limit) {
+            return GrowthStatus::Radius;
+        }
+        if conditions.stall_iterations.is_some_and(|limit| iteration >= self.last_growth + limit) {
+            return GrowthStatus::Stalled;
+        }
+        if conditions.mass_tolerance.is_some_and(|limit| self.mass_change.is_some_and(|change| change < limit)) {
+            return GrowthStatus::Converged;
+        }
+        return GrowthStatus::Growing;
+    }
+
+    /// Measure every cell of the simulation
+    fn measure(&mut self, sim: &dyn GrowthModel, edge_distance: &dyn Fn(usize, usize) -> usize) {
+        let mut frozen = Vec::new();
+        self.mass = 0.0;
+        for y in 0..sim.height() {
+            for x in 0..sim.width() {
+                if sim.is_frozen(x, y) {
+                    self.mass += sim.get_water(x, y);
+                    frozen.push((x, y));
+                }
+            }
+        }
+        self.frozen = 0;
+        self.edge_distance = None;
+        self.radius = 0.0;
+        self.stale = false;
+        self.add_frozen(&frozen, edge_distance);
+    }
+
+    /// Include cells which froze in the measurements, other than the mass
+    fn add_frozen(&mut self, cells: &[(usize, usize)], edge_distance: &dyn Fn(usize, usize) -> usize) {
+        let centers: Vec<(f64, f64)> = cells.iter().map(|&(x, y)| cell_center(x, y)).collect();
+        if self.origin.is_none() && !centers.is_empty() {
+            let count = centers.len() as f64;
+            self.origin = Some((
+                centers.iter().map(|c| c.0).sum::<f64>() / count,
+                centers.iter().map(|c| c.1).sum::<f64>() / count,
+            ));
+        }
+        if let Some(origin) = self.origin {
+            self.radius = centers.iter().map(|c| (c.0 - origin.0).hypot(c.1 - origin.1)).fold(self.radius, f64::max);
+        }
+        for &(x, y) in cells {
+            let distance = edge_distance(x, y);
+            self.edge_distance = Some(self.edge_distance.map_or(distance, |d| d.min(distance)));
+        }
+        self.frozen += cells.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::morphology;
+    use super::super::sim::{Boundary, SnowflakeSim};
+
+    /// Step a simulation until it stops, returns the iteration count
+    fn run(sim: &mut SnowflakeSim, conditions: StopConditions) -> usize {
+        sim.set_stop_conditions(conditions);
+        while !sim.status().is_stopped() && sim.iteration_count < 5000 {
+            sim.step();
+        }
+        return sim.iteration_count;
+    }
+
+    #[test]
+    fn test_stop_conditions() {
+        let (width, height) = (40, 40);
+        let new_sim = |beta: f64, gamma: f64| {
+            let mut sim = SnowflakeSim::new(width, height, 1.0, beta, gamma);
+            sim.set_water(width / 2, height / 2, 1.0);
+            return sim;
+        };
+
+        let mut sim = new_sim(0.6, 0.001);
+        sim.step();
+        assert_eq!(sim.status(), GrowthStatus::Growing);
+        let iterations = run(&mut sim, StopConditions { edge_distance: Some(2), ..StopConditions::default() });
+        assert_eq!(sim.status(), GrowthStatus::Edge);
+        assert!(iterations < 5000);
+        let near_edge = |x: usize, y: usize| x.min(y).min(width - 1 - x).min(height - 1 - y) <= 2;
+        assert!((0..height).any(|y| (0..width).any(|x| sim.get_water(x, y) >= 1.0 && near_edge(x, y))));
+
+        let mut sim = new_sim(0.6, 0.001);
+        run(&mut sim, StopConditions { edge_distance: Some(2), max_radius: Some(6.0), ..StopConditions::default() });
+        assert_eq!(sim.status(), GrowthStatus::Radius);
+        let radius = morphology::measure(&sim, (width / 2, height / 2)).max_radius;
+        assert!((6.0..8.0).contains(&radius));
+
+        // Absorbing boundaries drain the vapor until nothing freezes
+        let mut sim = new_sim(0.4, 0.0);
+        sim.set_boundary(Boundary::Absorbing);
+        run(&mut sim, StopConditions { stall_iterations: Some(200), ..StopConditions::default() });
+        assert_eq!(sim.status(), GrowthStatus::Stalled);
+
+        // Without vapor addition, the crystal in a closed grid grows until
+        // it used up the vapor around it
+        let mut sim = new_sim(0.4, 0.0);
+        sim.set_boundary(Boundary::Reflecting);
+        let iterations = run(&mut sim, StopConditions { mass_tolerance: Some(1e-3), ..StopConditions::default() });
+        assert_eq!(sim.status(), GrowthStatus::Converged);
+        assert!(iterations > 5 * MASS_WINDOW);
+        let area = |sim: &SnowflakeSim| morphology::measure(sim, (width / 2, height / 2)).area;
+        let converged = area(&sim);
+        assert!(converged > 300);
+        for _ in 0..500 {
+            sim.step();
+        }
+        assert!(area(&sim) - converged < converged / 50);
+    }
+
+    #[test]
+    fn test_incremental_tracking() {
+        // Measuring only the stepped cells must match measuring every cell,
+        // with the active region, every cell stepped and symmetric mode
+        for mode in 0..4 {
+            let new_sim = || {
+                let mut sim = SnowflakeSim::new(60, 60, 1.0, 0.4, 0.001);
+                match mode {
+                    1 => { sim.set_param("alpha_rand", 0.2); }
+                    2 => sim.enable_symmetry(30, 30),
+                    3 => { sim.set_boundary(Boundary::Circular); }
+                    _ => {}
+                }
+                sim.set_water(30, 30, 1.0);
+                sim.set_stop_conditions(StopConditions { max_radius: Some(100.0), ..StopConditions::default() });
+                return sim;
+            };
+            let (mut tracked, mut measured) = (new_sim(), new_sim());
+            for i in 0..300 {
+                if i == 150 {
+                    tracked.set_water(12, 40, 1.0);
+                    measured.set_water(12, 40, 1.0);
+                }
+                tracked.step();
+                // Setting the boundary makes the next step measure every cell
+                measured.set_boundary(measured.boundary());
+                measured.step();
+            }
+            let (a, b) = (tracked.growth(), measured.growth());
+            assert!(a.frozen > 100);
+            assert_eq!((a.frozen, a.last_growth, a.edge_distance, a.radius), (b.frozen, b.last_growth, b.edge_distance, b.radius));
+            assert!((a.mass - b.mass).abs() < 1e-9 * b.mass);
+        }
+    }
+}